@@ -0,0 +1,104 @@
+//! Decode MQTT packets from hex and print them.
+//!
+//! ```text
+//! mqtt-decode 30 09 00 03 61 2f 62 00 68 69 21
+//! echo "0x30,0x09,0x00,0x03,..." | mqtt-decode
+//! ```
+//!
+//! The hex may be split by whitespace, commas, or `0x` prefixes, and can hold several packets back to back.
+
+use nom::{Err as NomErr, Needed};
+use nom_mqtt::bytes::read_variable_bytes;
+use nom_mqtt::error::Error;
+use nom_mqtt::packet::PacketType;
+use nom_mqtt::parse;
+use std::convert::TryFrom;
+use std::io::Read;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("usage: mqtt-decode [HEX...]\n\nreads hex from stdin when no HEX is given");
+        return;
+    }
+    let hex = if args.is_empty() {
+        let mut hex = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut hex) {
+            eprintln!("failed to read stdin: {}", e);
+            process::exit(1);
+        }
+        hex
+    } else {
+        args.join(" ")
+    };
+    let bytes = match decode_hex(&hex) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut input = &bytes[..];
+    while !input.is_empty() {
+        let offset = bytes.len() - input.len();
+        match parse(input) {
+            Ok((next_input, packet)) => {
+                print!("[{}] {}", offset, packet);
+                input = next_input;
+            }
+            Err(NomErr::Incomplete(Needed::Size(size))) => {
+                eprintln!("[{}] incomplete packet: {} more bytes needed", offset, size);
+                process::exit(1);
+            }
+            Err(NomErr::Incomplete(Needed::Unknown)) => {
+                eprintln!("[{}] incomplete packet", offset);
+                process::exit(1);
+            }
+            Err(NomErr::Error(e)) | Err(NomErr::Failure(e)) => {
+                let packet_type = match PacketType::try_from(input[0] >> 4) {
+                    Ok(packet_type) => format!("{:?}", packet_type),
+                    Err(_) => format!("reserved type {}", input[0] >> 4),
+                };
+                match read_variable_bytes(&input[1..]) {
+                    Ok((_, (remaining_length, length))) => eprintln!(
+                        "[{}] malformed {} packet ({} bytes): {:?}",
+                        offset,
+                        packet_type,
+                        1 + length + remaining_length,
+                        Error::from(e.clone()),
+                    ),
+                    Err(_) => eprintln!(
+                        "[{}] malformed {} packet: {:?}",
+                        offset,
+                        packet_type,
+                        Error::from(e.clone()),
+                    ),
+                }
+                for (remaining, kind) in e.errors {
+                    eprintln!("  at byte {}: {:?}", bytes.len() - remaining.len(), kind);
+                }
+                process::exit(1);
+            }
+        }
+    }
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = hex
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|token| token.trim_start_matches("0x").trim_start_matches("0X"))
+        .flat_map(|token| token.bytes())
+        .collect();
+    let pairs = digits.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err("odd number of hex digits".to_string());
+    }
+    pairs
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).map_err(|e| e.to_string())?;
+            u8::from_str_radix(pair, 16).map_err(|_| format!("invalid hex byte {:?}", pair))
+        })
+        .collect()
+}
@@ -1,44 +1,33 @@
+use crate::error::{DecodeError, Error};
 use crate::Res;
-use nom::bytes::complete::take;
-use nom::error::{context, ErrorKind, VerboseError};
-use nom::multi::{fold_many_m_n, length_data};
+use nom::combinator::map_res;
+use nom::error::{context, ContextError, ErrorKind, FromExternalError};
+use nom::multi::length_data;
 use nom::number::complete::be_u16;
-use nom::sequence::pair;
-use nom::{Err as NomErr, InputTakeAtPosition};
+use nom::Err as NomErr;
+use std::convert::TryFrom;
 use std::str;
 
-fn is_variable_bytes_end<T>(i: T) -> Res<T, T>
-where
-    T: InputTakeAtPosition<Item = u8>,
-{
-    i.split_at_position1_complete(|item| item & 0x80 == 0, ErrorKind::HexDigit)
-}
-
+/// Read a Variable Byte Integer, returning its value and the number of bytes it took.
+/// It is at most 4 bytes long, a longer one is malformed
 pub fn read_variable_bytes(input: &[u8]) -> Res<&[u8], (usize, usize)> {
-    context(
-        "read variable bytes",
-        pair(
-            fold_many_m_n(
-                0,
-                3,
-                is_variable_bytes_end,
-                Vec::new(),
-                |mut acc: Vec<_>, item| {
-                    acc.extend_from_slice(item);
-                    acc
-                },
-            ),
-            take(1usize),
-        ),
-    )(input)
-    .map(|(next_input, mut res)| {
-        res.0.extend_from_slice(res.1);
-        let mut remain_len = 0;
-        for i in 0..res.0.len() {
-            remain_len += (res.0[i] as usize & 0x7F) << (i * 7);
+    let mut value = 0;
+    for (position, byte) in input.iter().take(4).enumerate() {
+        value += (*byte as usize & 0x7F) << (7 * position);
+        if byte & 0x80 == 0 {
+            return Ok((&input[position + 1..], (value, position + 1)));
         }
-        (next_input, (remain_len, res.0.len()))
-    })
+    }
+    let kind = if input.len() < 4 {
+        ErrorKind::Eof
+    } else {
+        ErrorKind::TooLarge
+    };
+    Err(NomErr::Error(DecodeError::add_context(
+        input,
+        "read variable bytes",
+        DecodeError::from_external_error(input, kind, Error::MalformedVariableByteInteger),
+    )))
 }
 
 /// named!(pub read_bytes<&[u8], &[u8]>, length_data!(be_u16));
@@ -47,23 +36,76 @@ pub fn read_bytes(input: &[u8]) -> Res<&[u8], &[u8]> {
 }
 
 pub fn read_string(input: &[u8]) -> Res<&[u8], &str> {
-    context("read string", length_data(be_u16))(input).and_then(|(next_input, res)| {
-        match str::from_utf8(res) {
-            Ok(s) => Ok((next_input, s)),
-            Err(_) => Err(NomErr::Error(VerboseError { errors: vec![] })),
+    context(
+        "read string",
+        map_res(length_data(be_u16), |res| {
+            str::from_utf8(res).map_err(|err| Error::InvalidString(err.to_string()))
+        }),
+    )(input)
+}
+
+/// Largest value a Variable Byte Integer of 4 bytes can hold
+pub const MAX_VARIABLE_BYTES: usize = 268_435_455;
+
+/// Fails with `InvalidLength`, writing nothing, when `value` is over `MAX_VARIABLE_BYTES`
+pub fn write_variable_bytes(mut value: usize, buf: &mut Vec<u8>) -> Result<(), Error> {
+    if value > MAX_VARIABLE_BYTES {
+        return Err(Error::InvalidLength);
+    }
+    loop {
+        let mut byte = (value % 128) as u8;
+        value /= 128;
+        if value > 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if value == 0 {
+            break;
         }
-    })
+    }
+    Ok(())
+}
+
+/// Number of bytes the Variable Byte Integer encoding of `value` takes. Over 4 for a value
+/// above `MAX_VARIABLE_BYTES`, which `write_variable_bytes` refuses
+pub fn variable_bytes_len(mut value: usize) -> usize {
+    let mut len = 1;
+    while value > 0x7F {
+        value >>= 7;
+        len += 1;
+    }
+    len
+}
+
+/// Fails with `InvalidLength`, writing nothing, when `bytes` is longer than 65535 bytes
+pub fn write_bytes(bytes: &[u8], buf: &mut Vec<u8>) -> Result<(), Error> {
+    let len = u16::try_from(bytes.len()).map_err(|_| Error::InvalidLength)?;
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(bytes);
+    Ok(())
+}
+
+pub fn write_string(string: &str, buf: &mut Vec<u8>) -> Result<(), Error> {
+    write_bytes(string.as_bytes(), buf)
 }
 
 #[cfg(test)]
 mod test_bytes {
-    use crate::bytes::{read_string, read_variable_bytes};
+    use crate::bytes::{
+        read_string, read_variable_bytes, variable_bytes_len, write_string, write_variable_bytes,
+    };
+    use crate::error::Error;
 
     #[test]
     fn test_read_variable_bytes() {
         let bytes = &[0];
         let variable_bytes = read_variable_bytes(bytes);
         println!("{:?}", variable_bytes);
+        // at most 4 bytes
+        assert!(read_variable_bytes(&[0x80, 0x80, 0x80, 0x80, 0x00]).is_err());
+        assert!(read_variable_bytes(&[0xFF; 16]).is_err());
+        // truncated
+        assert!(read_variable_bytes(&[0x80, 0x80]).is_err());
     }
 
     #[test]
@@ -72,4 +114,50 @@ mod test_bytes {
         let variable_bytes = read_string(bytes);
         assert_eq!(variable_bytes, Ok((&vec![][..], "iamazy")));
     }
+
+    #[test]
+    fn test_write_variable_bytes() {
+        for (value, bytes) in &[
+            (0usize, vec![0x00]),
+            (127, vec![0x7F]),
+            (128, vec![0x80, 0x01]),
+            (16_383, vec![0xFF, 0x7F]),
+            (16_384, vec![0x80, 0x80, 0x01]),
+            (2_097_151, vec![0xFF, 0xFF, 0x7F]),
+            (2_097_152, vec![0x80, 0x80, 0x80, 0x01]),
+            (268_435_455, vec![0xFF, 0xFF, 0xFF, 0x7F]),
+        ] {
+            let mut buf = Vec::new();
+            write_variable_bytes(*value, &mut buf).unwrap();
+            assert_eq!(&buf, bytes);
+            assert_eq!(variable_bytes_len(*value), bytes.len());
+            assert_eq!(
+                read_variable_bytes(&buf),
+                Ok((&vec![][..], (*value, bytes.len())))
+            );
+        }
+        let mut buf = Vec::new();
+        assert_eq!(
+            write_variable_bytes(268_435_456, &mut buf),
+            Err(Error::InvalidLength)
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_write_string() {
+        let mut buf = Vec::new();
+        write_string("iamazy", &mut buf).unwrap();
+        assert_eq!(buf, vec![0, 6, 105, 97, 109, 97, 122, 121]);
+
+        let mut buf = Vec::new();
+        assert!(write_string(&"a".repeat(65_535), &mut buf).is_ok());
+        assert_eq!(buf.len(), 65_537);
+        let mut buf = Vec::new();
+        assert_eq!(
+            write_string(&"a".repeat(65_536), &mut buf),
+            Err(Error::InvalidLength)
+        );
+        assert!(buf.is_empty());
+    }
 }
@@ -0,0 +1,304 @@
+use crate::packet::{FixedHeader, Mqtt5Property, Packet, PropertyType, PropertyValue, Qos};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Binary data longer than this is truncated when displayed
+const MAX_DISPLAY_BYTES: usize = 32;
+
+/// Multi-line rendering of a packet meant for debugging, e.g.
+///
+/// ```text
+/// PUBLISH dup=false qos=1 retain=false remaining_length=12
+///   topic_name: "a/b"
+///   packet_id: 10
+///   properties:
+///     PayloadFormatIndicator: true
+///   payload (2 bytes): 6869
+/// ```
+impl fmt::Display for Packet<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Packet::Connect(connect) => {
+                let variable_header = &connect.variable_header;
+                let connect_flags = &variable_header.connect_flags;
+                let payload = &connect.payload;
+                fixed_header(f, &connect.fixed_header)?;
+                writeln!(f, "  protocol: {:?}", variable_header.protocol)?;
+                writeln!(
+                    f,
+                    "  connect_flags: clean_start={} will_flag={} will_qos={} will_retain={} username_flag={} password_flag={}",
+                    connect_flags.clean_start,
+                    connect_flags.will_flag,
+                    connect_flags.will_qos as u8,
+                    connect_flags.will_retain,
+                    connect_flags.username_flag,
+                    connect_flags.password_flag,
+                )?;
+                writeln!(f, "  keep_alive: {}", variable_header.keep_alive)?;
+                properties(f, "properties", &variable_header.connect_property)?;
+                writeln!(f, "  client_id: {:?}", payload.client_id)?;
+                if let Some(will_property) = &payload.will_property {
+                    properties(f, "will_properties", will_property)?;
+                }
+                if let Some(will_topic) = payload.will_topic {
+                    writeln!(f, "  will_topic: {:?}", will_topic)?;
+                }
+                if let Some(will_payload) = payload.will_payload {
+                    writeln!(f, "  will_payload: {}", Hex(will_payload))?;
+                }
+                if let Some(username) = payload.username {
+                    writeln!(f, "  username: {:?}", username)?;
+                }
+                if payload.password.is_some() {
+                    writeln!(f, "  password: ***")?;
+                }
+                Ok(())
+            }
+            Packet::ConnAck(connack) => {
+                let variable_header = &connack.variable_header;
+                fixed_header(f, &connack.fixed_header)?;
+                writeln!(
+                    f,
+                    "  session_present: {}",
+                    variable_header.connack_flags.session_present
+                )?;
+                writeln!(
+                    f,
+                    "  reason_code: {:?}",
+                    variable_header.connect_reason_code
+                )?;
+                properties(f, "properties", &variable_header.connack_property)
+            }
+            Packet::Publish(publish) => {
+                let variable_header = &publish.variable_header;
+                fixed_header(f, &publish.fixed_header)?;
+                writeln!(f, "  topic_name: {:?}", variable_header.topic_name)?;
+                if publish.fixed_header.qos != Qos::AtMostOnce {
+                    writeln!(f, "  packet_id: {}", variable_header.packet_id)?;
+                }
+                properties(f, "properties", &variable_header.publish_property)?;
+                writeln!(
+                    f,
+                    "  payload ({} bytes): {}",
+                    publish.payload.len(),
+                    Hex(publish.payload)
+                )
+            }
+            Packet::PubAck(puback) => {
+                let variable_header = &puback.variable_header;
+                fixed_header(f, &puback.fixed_header)?;
+                writeln!(f, "  packet_id: {}", variable_header.packet_id)?;
+                writeln!(f, "  reason_code: {:?}", variable_header.puback_reason_code)?;
+                properties(f, "properties", &variable_header.puback_property)
+            }
+            Packet::PubRec(pubrec) => {
+                let variable_header = &pubrec.variable_header;
+                fixed_header(f, &pubrec.fixed_header)?;
+                writeln!(f, "  packet_id: {}", variable_header.packet_id)?;
+                writeln!(f, "  reason_code: {:?}", variable_header.pubrec_reason_code)?;
+                properties(f, "properties", &variable_header.pubrec_property)
+            }
+            Packet::PubRel(pubrel) => {
+                let variable_header = &pubrel.variable_header;
+                fixed_header(f, &pubrel.fixed_header)?;
+                writeln!(f, "  packet_id: {}", variable_header.packet_id)?;
+                writeln!(f, "  reason_code: {:?}", variable_header.pubrel_reason_code)?;
+                properties(f, "properties", &variable_header.pubrel_property)
+            }
+            Packet::PubComp(pubcomp) => {
+                let variable_header = &pubcomp.variable_header;
+                fixed_header(f, &pubcomp.fixed_header)?;
+                writeln!(f, "  packet_id: {}", variable_header.packet_id)?;
+                writeln!(
+                    f,
+                    "  reason_code: {:?}",
+                    variable_header.pubcomp_reason_code
+                )?;
+                properties(f, "properties", &variable_header.pubcomp_property)
+            }
+            Packet::Subscribe(subscribe) => {
+                let variable_header = &subscribe.variable_header;
+                fixed_header(f, &subscribe.fixed_header)?;
+                writeln!(f, "  packet_id: {}", variable_header.packet_id)?;
+                properties(f, "properties", &variable_header.subscribe_property)?;
+                for (topic_filter, options) in &subscribe.payload {
+                    writeln!(
+                        f,
+                        "  topic_filter: {:?} maximum_qos={} no_local={} retain_as_published={} retain_handling={}",
+                        topic_filter,
+                        options.maximum_qos as u8,
+                        options.no_local,
+                        options.retain_as_published,
+                        options.retain_handling,
+                    )?;
+                }
+                Ok(())
+            }
+            Packet::SubAck(suback) => {
+                let variable_header = &suback.variable_header;
+                fixed_header(f, &suback.fixed_header)?;
+                writeln!(f, "  packet_id: {}", variable_header.packet_id)?;
+                properties(f, "properties", &variable_header.suback_property)?;
+                for reason_code in &suback.payload {
+                    writeln!(f, "  reason_code: {:?}", reason_code)?;
+                }
+                Ok(())
+            }
+            Packet::UnSubscribe(unsubscribe) => {
+                let variable_header = &unsubscribe.variable_header;
+                fixed_header(f, &unsubscribe.fixed_header)?;
+                writeln!(f, "  packet_id: {}", variable_header.packet_id)?;
+                properties(f, "properties", &variable_header.unsubscribe_property)?;
+                for topic_filter in &unsubscribe.payload {
+                    writeln!(f, "  topic_filter: {:?}", topic_filter)?;
+                }
+                Ok(())
+            }
+            Packet::UnSubAck(unsuback) => {
+                let variable_header = &unsuback.variable_header;
+                fixed_header(f, &unsuback.fixed_header)?;
+                writeln!(f, "  packet_id: {}", variable_header.packet_id)?;
+                properties(f, "properties", &variable_header.unsuback_property)?;
+                for reason_code in &unsuback.payload {
+                    writeln!(f, "  reason_code: {:?}", reason_code)?;
+                }
+                Ok(())
+            }
+            Packet::PingReq(ping_req) => fixed_header(f, &ping_req.fixed_header),
+            Packet::PingResp(ping_resp) => fixed_header(f, &ping_resp.fixed_header),
+            Packet::Disconnect(disconnect) => {
+                let variable_header = &disconnect.variable_header;
+                fixed_header(f, &disconnect.fixed_header)?;
+                writeln!(
+                    f,
+                    "  reason_code: {:?}",
+                    variable_header.disconnect_reason_code
+                )?;
+                properties(f, "properties", &variable_header.disconnect_property)
+            }
+            Packet::Auth(auth) => {
+                let variable_header = &auth.variable_header;
+                fixed_header(f, &auth.fixed_header)?;
+                writeln!(f, "  reason_code: {:?}", variable_header.auth_reason_code)?;
+                properties(f, "properties", &variable_header.auth_property)
+            }
+        }
+    }
+}
+
+fn fixed_header(f: &mut fmt::Formatter, fixed_header: &FixedHeader) -> fmt::Result {
+    writeln!(
+        f,
+        "{:?} dup={} qos={} retain={} remaining_length={}",
+        fixed_header.packet_type,
+        fixed_header.dup,
+        fixed_header.qos as u8,
+        fixed_header.retain,
+        fixed_header.remaining_length,
+    )
+}
+
+fn properties(f: &mut fmt::Formatter, name: &str, property: &Mqtt5Property) -> fmt::Result {
+    if property.properties.is_empty() && property.user_properties.is_empty() {
+        return Ok(());
+    }
+    writeln!(f, "  {}:", name)?;
+    let mut property_ids: Vec<_> = property.properties.keys().collect();
+    property_ids.sort();
+    for property_id in property_ids {
+        let property_name = match PropertyType::try_from(*property_id as u8) {
+            Ok(property_type) => format!("{:?}", property_type),
+            Err(_) => format!("{:#04x}", property_id),
+        };
+        match &property.properties[property_id] {
+            PropertyValue::Multiple(property_values) => {
+                for property_value in property_values {
+                    writeln!(f, "    {}: {}", property_name, Value(property_value))?;
+                }
+            }
+            property_value => writeln!(f, "    {}: {}", property_name, Value(property_value))?,
+        }
+    }
+    for (key, value) in &property.user_properties {
+        writeln!(f, "    UserProperty: {:?} = {:?}", key, value)?;
+    }
+    Ok(())
+}
+
+struct Value<'a, 'b>(&'b PropertyValue<'a>);
+
+impl fmt::Display for Value<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            PropertyValue::Bit(bit) => write!(f, "{}", bit),
+            PropertyValue::Byte(byte) => write!(f, "{}", byte),
+            PropertyValue::TwoByteInteger(value) => write!(f, "{}", value),
+            PropertyValue::FourByteInteger(value) => write!(f, "{}", value),
+            PropertyValue::VariableByteInteger(value) => write!(f, "{}", value),
+            PropertyValue::String(string) => write!(f, "{:?}", string),
+            PropertyValue::Binary(bytes) => write!(f, "{}", Hex(bytes)),
+            PropertyValue::StringPair(key, value) => write!(f, "{:?} = {:?}", key, value),
+            PropertyValue::Multiple(property_values) => {
+                for (i, property_value) in property_values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", Value(property_value))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.0.iter().take(MAX_DISPLAY_BYTES) {
+            write!(f, "{:02x}", byte)?;
+        }
+        if self.0.len() > MAX_DISPLAY_BYTES {
+            write!(f, "...")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test_display {
+    use crate::parse;
+
+    #[test]
+    fn test_display_publish() {
+        let bytes = &[
+            0b0011_0010, 24,
+            0x00, 0x03, b'a', b'/', b'b', // topic name
+            0x00, 0x0A, // packet identifier
+            9,
+            0x01, 0x01, // payload format indicator
+            0x26, 0x00, 0x01, b'k', 0x00, 0x01, b'v', // user property
+            b'h', b'e', b'l', b'l', b'o', b'!', b'!',
+        ];
+        let (_, packet) = parse(bytes).unwrap();
+        assert_eq!(
+            packet.to_string(),
+            "PUBLISH dup=false qos=1 retain=false remaining_length=24\n\
+             \x20 topic_name: \"a/b\"\n\
+             \x20 packet_id: 10\n\
+             \x20 properties:\n\
+             \x20   PayloadFormatIndicator: true\n\
+             \x20   UserProperty: \"k\" = \"v\"\n\
+             \x20 payload (7 bytes): 68656c6c6f2121\n"
+        );
+    }
+
+    #[test]
+    fn test_display_truncated_payload() {
+        let mut bytes = vec![0b0011_0000, 68, 0x00, 0x01, b't', 0];
+        bytes.extend_from_slice(&[0xAB; 64]);
+        let (_, packet) = parse(&bytes).unwrap();
+        assert!(packet.to_string().ends_with(&format!("payload (64 bytes): {}...\n", "ab".repeat(32))));
+    }
+}
@@ -0,0 +1,637 @@
+use crate::bytes::{
+    variable_bytes_len, write_bytes, write_string, write_variable_bytes, MAX_VARIABLE_BYTES,
+};
+use crate::error::Error;
+use crate::packet::{
+    Auth, ConnAck, Connect, Disconnect, FixedHeader, Mqtt5Property, Packet, PropertyValue,
+    Protocol, PubAck, PubComp, PubRec, PubRel, Publish, Qos, SubAck, Subscribe, UnSubAck,
+    UnSubscribe,
+};
+
+/// Serialize a packet into a new buffer
+pub fn to_bytes(packet: &Packet) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    encode_into(packet, &mut buf)?;
+    Ok(buf)
+}
+
+/// Append the serialized packet to `buf`.
+///
+/// The Remaining Length and every Property Length are calculated from the packet content,
+/// the `remaining_length` and `property_length` fields of the packet are ignored. They are
+/// known before anything is written, so `buf` is grown at most once.
+///
+/// Fails with `InvalidLength` when a length does not fit its field on the wire: a String or
+/// Binary Data over 65535 bytes, or a Remaining Length over `MAX_VARIABLE_BYTES`. A packet the
+/// parser would refuse fails as well: `InvalidQos` for `Qos::Reserved`, `InvalidPacketId` for
+/// a Packet Identifier of 0, and `MalformedPacket` for a Retain Handling over 2. Nothing is
+/// left in `buf` then.
+pub fn encode_into(packet: &Packet, buf: &mut Vec<u8>) -> Result<(), Error> {
+    let remaining_length = remaining_length(packet);
+    if remaining_length > MAX_VARIABLE_BYTES {
+        return Err(Error::InvalidLength);
+    }
+    buf.reserve(1 + variable_bytes_len(remaining_length) + remaining_length);
+    let fixed_header = match packet {
+        Packet::Connect(connect) => &connect.fixed_header,
+        Packet::ConnAck(connack) => &connack.fixed_header,
+        Packet::Publish(publish) => &publish.fixed_header,
+        Packet::PubAck(puback) => &puback.fixed_header,
+        Packet::PubRec(pubrec) => &pubrec.fixed_header,
+        Packet::PubRel(pubrel) => &pubrel.fixed_header,
+        Packet::PubComp(pubcomp) => &pubcomp.fixed_header,
+        Packet::Subscribe(subscribe) => &subscribe.fixed_header,
+        Packet::SubAck(suback) => &suback.fixed_header,
+        Packet::UnSubscribe(unsubscribe) => &unsubscribe.fixed_header,
+        Packet::UnSubAck(unsuback) => &unsuback.fixed_header,
+        Packet::PingReq(ping_req) => &ping_req.fixed_header,
+        Packet::PingResp(ping_resp) => &ping_resp.fixed_header,
+        Packet::Disconnect(disconnect) => &disconnect.fixed_header,
+        Packet::Auth(auth) => &auth.fixed_header,
+    };
+    let start = buf.len();
+    if let Err(err) = packet_into(packet, fixed_header, remaining_length, buf) {
+        buf.truncate(start);
+        return Err(err);
+    }
+    Ok(())
+}
+
+fn packet_into(
+    packet: &Packet,
+    fixed_header: &FixedHeader,
+    remaining_length: usize,
+    buf: &mut Vec<u8>,
+) -> Result<(), Error> {
+    fixed_header_into(fixed_header, remaining_length, buf)?;
+    match packet {
+        Packet::Connect(connect) => connect_into(connect, buf),
+        Packet::ConnAck(connack) => connack_into(connack, buf),
+        Packet::Publish(publish) => publish_into(publish, buf),
+        Packet::PubAck(puback) => puback_into(puback, buf),
+        Packet::PubRec(pubrec) => pubrec_into(pubrec, buf),
+        Packet::PubRel(pubrel) => pubrel_into(pubrel, buf),
+        Packet::PubComp(pubcomp) => pubcomp_into(pubcomp, buf),
+        Packet::Subscribe(subscribe) => subscribe_into(subscribe, buf),
+        Packet::SubAck(suback) => suback_into(suback, buf),
+        Packet::UnSubscribe(unsubscribe) => unsubscribe_into(unsubscribe, buf),
+        Packet::UnSubAck(unsuback) => unsuback_into(unsuback, buf),
+        Packet::PingReq(_) | Packet::PingResp(_) => Ok(()),
+        Packet::Disconnect(disconnect) => disconnect_into(disconnect, buf),
+        Packet::Auth(auth) => auth_into(auth, buf),
+    }
+}
+
+impl Packet<'_> {
+    /// Number of bytes `encode_into` writes for this packet, fixed header included.
+    ///
+    /// Useful to preallocate a buffer or to check a Maximum Packet Size before encoding. A packet
+    /// too large for the wire format gets its true size here, and is refused by `encode_into`.
+    pub fn encoded_len(&self) -> usize {
+        let remaining_length = remaining_length(self);
+        1 + variable_bytes_len(remaining_length) + remaining_length
+    }
+}
+
+impl Mqtt5Property<'_> {
+    /// Number of bytes the property block takes in a packet, Property Length included
+    pub fn encoded_len(&self) -> usize {
+        let properties_len = properties_len(self);
+        variable_bytes_len(properties_len) + properties_len
+    }
+}
+
+fn remaining_length(packet: &Packet) -> usize {
+    match packet {
+        Packet::Connect(connect) => connect_len(connect),
+        Packet::ConnAck(connack) => 2 + connack.variable_header.connack_property.encoded_len(),
+        Packet::Publish(publish) => publish_len(publish),
+        Packet::PubAck(puback) => 3 + puback.variable_header.puback_property.encoded_len(),
+        Packet::PubRec(pubrec) => 3 + pubrec.variable_header.pubrec_property.encoded_len(),
+        Packet::PubRel(pubrel) => 3 + pubrel.variable_header.pubrel_property.encoded_len(),
+        Packet::PubComp(pubcomp) => 3 + pubcomp.variable_header.pubcomp_property.encoded_len(),
+        Packet::Subscribe(subscribe) => {
+            2 + subscribe.variable_header.subscribe_property.encoded_len()
+                + subscribe
+                    .payload
+                    .iter()
+                    .map(|(topic_filter, _)| string_len(topic_filter) + 1)
+                    .sum::<usize>()
+        }
+        Packet::SubAck(suback) => {
+            2 + suback.variable_header.suback_property.encoded_len() + suback.payload.len()
+        }
+        Packet::UnSubscribe(unsubscribe) => {
+            2 + unsubscribe
+                .variable_header
+                .unsubscribe_property
+                .encoded_len()
+                + unsubscribe
+                    .payload
+                    .iter()
+                    .map(|topic_filter| string_len(topic_filter))
+                    .sum::<usize>()
+        }
+        Packet::UnSubAck(unsuback) => {
+            2 + unsuback.variable_header.unsuback_property.encoded_len() + unsuback.payload.len()
+        }
+        Packet::PingReq(_) | Packet::PingResp(_) => 0,
+        Packet::Disconnect(disconnect) => {
+            1 + disconnect.variable_header.disconnect_property.encoded_len()
+        }
+        Packet::Auth(auth) => 1 + auth.variable_header.auth_property.encoded_len(),
+    }
+}
+
+fn connect_len(connect: &Connect) -> usize {
+    let variable_header = &connect.variable_header;
+    let connect_flags = &variable_header.connect_flags;
+    let payload = &connect.payload;
+    let mut len = match variable_header.protocol {
+        Protocol::MQTT5 => string_len("MQTT") + 1,
+    };
+    // connect flags and keep alive
+    len += 1 + 2;
+    len += variable_header.connect_property.encoded_len();
+    len += string_len(payload.client_id);
+    if connect_flags.will_flag {
+        len += match &payload.will_property {
+            Some(will_property) => will_property.encoded_len(),
+            None => 1,
+        };
+        len += string_len(payload.will_topic.unwrap_or_default());
+        len += 2 + payload.will_payload.unwrap_or_default().len();
+    }
+    if connect_flags.username_flag {
+        len += string_len(payload.username.unwrap_or_default());
+    }
+    if connect_flags.password_flag {
+        len += string_len(payload.password.unwrap_or_default());
+    }
+    len
+}
+
+fn publish_len(publish: &Publish) -> usize {
+    let variable_header = &publish.variable_header;
+    let packet_id_len = match publish.fixed_header.qos {
+        Qos::AtMostOnce => 0,
+        _ => 2,
+    };
+    string_len(variable_header.topic_name)
+        + packet_id_len
+        + variable_header.publish_property.encoded_len()
+        + publish.payload.len()
+}
+
+fn string_len(string: &str) -> usize {
+    2 + string.len()
+}
+
+/// Property Length excluded
+fn properties_len(property: &Mqtt5Property) -> usize {
+    property
+        .properties
+        .values()
+        .map(property_value_len)
+        .sum::<usize>()
+        + property
+            .user_properties
+            .iter()
+            .map(|(name, value)| 1 + string_len(name) + string_len(value))
+            .sum::<usize>()
+}
+
+/// Property Identifier included
+fn property_value_len(property_value: &PropertyValue) -> usize {
+    1 + match property_value {
+        PropertyValue::Bit(_) | PropertyValue::Byte(_) => 1,
+        PropertyValue::TwoByteInteger(_) => 2,
+        PropertyValue::FourByteInteger(_) => 4,
+        PropertyValue::String(string) => string_len(string),
+        PropertyValue::VariableByteInteger(value) => variable_bytes_len(*value),
+        PropertyValue::Binary(bytes) => 2 + bytes.len(),
+        PropertyValue::StringPair(name, value) => string_len(name) + string_len(value),
+        PropertyValue::Multiple(property_values) => {
+            return property_values.iter().map(property_value_len).sum();
+        }
+    }
+}
+
+fn fixed_header_into(
+    fixed_header: &FixedHeader,
+    remaining_length: usize,
+    buf: &mut Vec<u8>,
+) -> Result<(), Error> {
+    buf.push(
+        (fixed_header.packet_type.clone() as u8) << 4
+            | (fixed_header.dup as u8) << 3
+            | qos_bits(fixed_header.qos)? << 1
+            | fixed_header.retain as u8,
+    );
+    write_variable_bytes(remaining_length, buf)
+}
+
+/// `Qos::Reserved` is never sent
+fn qos_bits(qos: Qos) -> Result<u8, Error> {
+    match qos {
+        Qos::Reserved => Err(Error::InvalidQos(Qos::Reserved as u8)),
+        qos => Ok(qos as u8),
+    }
+}
+
+/// A Packet Identifier of 0 is a Protocol Error
+fn packet_id_into(packet_id: u16, buf: &mut Vec<u8>) -> Result<(), Error> {
+    if packet_id == 0 {
+        return Err(Error::InvalidPacketId);
+    }
+    buf.extend_from_slice(&packet_id.to_be_bytes());
+    Ok(())
+}
+
+fn auth_into(auth: &Auth, buf: &mut Vec<u8>) -> Result<(), Error> {
+    buf.push(auth.variable_header.auth_reason_code as u8);
+    mqtt5_property_into(&auth.variable_header.auth_property, buf)
+}
+
+fn connack_into(connack: &ConnAck, buf: &mut Vec<u8>) -> Result<(), Error> {
+    let variable_header = &connack.variable_header;
+    buf.push(variable_header.connack_flags.session_present as u8);
+    buf.push(variable_header.connect_reason_code as u8);
+    mqtt5_property_into(&variable_header.connack_property, buf)
+}
+
+fn connect_into(connect: &Connect, buf: &mut Vec<u8>) -> Result<(), Error> {
+    let variable_header = &connect.variable_header;
+    match variable_header.protocol {
+        Protocol::MQTT5 => {
+            write_string("MQTT", buf)?;
+            buf.push(5);
+        }
+    }
+    let connect_flags = &variable_header.connect_flags;
+    buf.push(
+        (connect_flags.username_flag as u8) << 7
+            | (connect_flags.password_flag as u8) << 6
+            | (connect_flags.will_retain as u8) << 5
+            | qos_bits(connect_flags.will_qos)? << 3
+            | (connect_flags.will_flag as u8) << 2
+            | (connect_flags.clean_start as u8) << 1,
+    );
+    buf.extend_from_slice(&variable_header.keep_alive.to_be_bytes());
+    mqtt5_property_into(&variable_header.connect_property, buf)?;
+
+    let payload = &connect.payload;
+    write_string(payload.client_id, buf)?;
+    if connect_flags.will_flag {
+        match &payload.will_property {
+            Some(will_property) => mqtt5_property_into(will_property, buf)?,
+            None => mqtt5_property_into(&Mqtt5Property::new(), buf)?,
+        }
+        write_string(payload.will_topic.unwrap_or_default(), buf)?;
+        write_bytes(payload.will_payload.unwrap_or_default(), buf)?;
+    }
+    if connect_flags.username_flag {
+        write_string(payload.username.unwrap_or_default(), buf)?;
+    }
+    if connect_flags.password_flag {
+        write_string(payload.password.unwrap_or_default(), buf)?;
+    }
+    Ok(())
+}
+
+fn disconnect_into(disconnect: &Disconnect, buf: &mut Vec<u8>) -> Result<(), Error> {
+    let variable_header = &disconnect.variable_header;
+    buf.push(variable_header.disconnect_reason_code as u8);
+    mqtt5_property_into(&variable_header.disconnect_property, buf)
+}
+
+fn puback_into(puback: &PubAck, buf: &mut Vec<u8>) -> Result<(), Error> {
+    let variable_header = &puback.variable_header;
+    packet_id_into(variable_header.packet_id, buf)?;
+    buf.push(variable_header.puback_reason_code as u8);
+    mqtt5_property_into(&variable_header.puback_property, buf)
+}
+
+fn pubcomp_into(pubcomp: &PubComp, buf: &mut Vec<u8>) -> Result<(), Error> {
+    let variable_header = &pubcomp.variable_header;
+    packet_id_into(variable_header.packet_id, buf)?;
+    buf.push(variable_header.pubcomp_reason_code as u8);
+    mqtt5_property_into(&variable_header.pubcomp_property, buf)
+}
+
+fn publish_into(publish: &Publish, buf: &mut Vec<u8>) -> Result<(), Error> {
+    let variable_header = &publish.variable_header;
+    write_string(variable_header.topic_name, buf)?;
+    if publish.fixed_header.qos != Qos::AtMostOnce {
+        packet_id_into(variable_header.packet_id, buf)?;
+    }
+    mqtt5_property_into(&variable_header.publish_property, buf)?;
+    buf.extend_from_slice(publish.payload);
+    Ok(())
+}
+
+fn pubrec_into(pubrec: &PubRec, buf: &mut Vec<u8>) -> Result<(), Error> {
+    let variable_header = &pubrec.variable_header;
+    packet_id_into(variable_header.packet_id, buf)?;
+    buf.push(variable_header.pubrec_reason_code as u8);
+    mqtt5_property_into(&variable_header.pubrec_property, buf)
+}
+
+fn pubrel_into(pubrel: &PubRel, buf: &mut Vec<u8>) -> Result<(), Error> {
+    let variable_header = &pubrel.variable_header;
+    packet_id_into(variable_header.packet_id, buf)?;
+    buf.push(variable_header.pubrel_reason_code as u8);
+    mqtt5_property_into(&variable_header.pubrel_property, buf)
+}
+
+fn suback_into(suback: &SubAck, buf: &mut Vec<u8>) -> Result<(), Error> {
+    let variable_header = &suback.variable_header;
+    packet_id_into(variable_header.packet_id, buf)?;
+    mqtt5_property_into(&variable_header.suback_property, buf)?;
+    buf.extend(suback.payload.iter().map(|reason_code| *reason_code as u8));
+    Ok(())
+}
+
+fn subscribe_into(subscribe: &Subscribe, buf: &mut Vec<u8>) -> Result<(), Error> {
+    let variable_header = &subscribe.variable_header;
+    packet_id_into(variable_header.packet_id, buf)?;
+    mqtt5_property_into(&variable_header.subscribe_property, buf)?;
+    for (topic_filter, options) in &subscribe.payload {
+        write_string(topic_filter, buf)?;
+        // Retain Handling takes 2 bits, and 3 is reserved
+        if options.retain_handling > 2 {
+            return Err(Error::MalformedPacket);
+        }
+        buf.push(
+            options.retain_handling << 4
+                | (options.retain_as_published as u8) << 3
+                | (options.no_local as u8) << 2
+                | qos_bits(options.maximum_qos)?,
+        );
+    }
+    Ok(())
+}
+
+fn unsuback_into(unsuback: &UnSubAck, buf: &mut Vec<u8>) -> Result<(), Error> {
+    let variable_header = &unsuback.variable_header;
+    packet_id_into(variable_header.packet_id, buf)?;
+    mqtt5_property_into(&variable_header.unsuback_property, buf)?;
+    buf.extend(
+        unsuback
+            .payload
+            .iter()
+            .map(|reason_code| *reason_code as u8),
+    );
+    Ok(())
+}
+
+fn unsubscribe_into(unsubscribe: &UnSubscribe, buf: &mut Vec<u8>) -> Result<(), Error> {
+    let variable_header = &unsubscribe.variable_header;
+    packet_id_into(variable_header.packet_id, buf)?;
+    mqtt5_property_into(&variable_header.unsubscribe_property, buf)?;
+    for topic_filter in &unsubscribe.payload {
+        write_string(topic_filter, buf)?;
+    }
+    Ok(())
+}
+
+/// Properties are written in ascending identifier order, followed by the User Properties
+/// in their original order
+fn mqtt5_property_into(property: &Mqtt5Property, buf: &mut Vec<u8>) -> Result<(), Error> {
+    write_variable_bytes(properties_len(property), buf)?;
+    let mut property_ids: Vec<_> = property.properties.keys().collect();
+    property_ids.sort();
+    for property_id in property_ids {
+        property_value_into(*property_id as u8, &property.properties[property_id], buf)?;
+    }
+    for (name, value) in &property.user_properties {
+        buf.push(0x26);
+        write_string(name, buf)?;
+        write_string(value, buf)?;
+    }
+    Ok(())
+}
+
+fn property_value_into(
+    property_id: u8,
+    property_value: &PropertyValue,
+    buf: &mut Vec<u8>,
+) -> Result<(), Error> {
+    if let PropertyValue::Multiple(property_values) = property_value {
+        for property_value in property_values {
+            property_value_into(property_id, property_value, buf)?;
+        }
+        return Ok(());
+    }
+    buf.push(property_id);
+    match property_value {
+        PropertyValue::Bit(bit) => buf.push(*bit as u8),
+        PropertyValue::Byte(byte) => buf.push(*byte),
+        PropertyValue::TwoByteInteger(value) => buf.extend_from_slice(&value.to_be_bytes()),
+        PropertyValue::FourByteInteger(value) => buf.extend_from_slice(&value.to_be_bytes()),
+        PropertyValue::String(string) => write_string(string, buf)?,
+        PropertyValue::VariableByteInteger(value) => write_variable_bytes(*value, buf)?,
+        PropertyValue::Binary(bytes) => write_bytes(bytes, buf)?,
+        PropertyValue::StringPair(name, value) => {
+            write_string(name, buf)?;
+            write_string(value, buf)?;
+        }
+        PropertyValue::Multiple(_) => unreachable!(),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test_encode {
+    use crate::encode::{encode_into, to_bytes};
+    use crate::error::Error;
+    use crate::packet::{Packet, Qos};
+    use crate::{mqtt5_property, parse};
+
+    fn round_trip(bytes: &[u8]) {
+        let (_, packet) = parse(bytes).unwrap();
+        assert_eq!(to_bytes(&packet).unwrap(), bytes);
+        assert_eq!(packet.encoded_len(), bytes.len());
+    }
+
+    #[test]
+    fn test_connect() {
+        round_trip(&[
+            0b0001_0000, 52, // fixed header
+            0x00, 0x04, b'M', b'Q', b'T', b'T', // protocol name
+            0x05,          // protocol version
+            0b1100_1110,   // connect flag
+            0x00, 0x10, // keep alive
+            0x05, 0x11, 0x00, 0x00, 0x00, 0x10, // connect properties
+            0x00, 0x03, b'c', b'i', b'd', // client id
+            0x05, 0x02, 0x00, 0x00, 0x00, 0x10, // will properties
+            0x00, 0x04, b'w', b'i', b'l', b'l', // will topic
+            0x00, 0x01, b'p', // will payload
+            0x00, 0x06, b'i', b'a', b'm', b'a', b'z', b'y', // username
+            0x00, 0x06, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, // password
+        ]);
+    }
+
+    #[test]
+    fn test_connack() {
+        round_trip(&[
+            0b0010_0000, 8,
+            0x01, 0x00, // session present, success
+            5, 0x11, 0x00, 0x00, 0x00, 0x78, // session expiry interval
+        ]);
+    }
+
+    #[test]
+    fn test_publish() {
+        // qos 0 carries no packet identifier
+        round_trip(&[
+            0b0011_0001, 9,
+            0x00, 0x03, b'a', b'/', b'b', // topic name
+            0, // properties
+            b'h', b'i', b'!',
+        ]);
+        round_trip(&[
+            0b0011_1100, 22,
+            0x00, 0x03, b'a', b'/', b'b', // topic name
+            0x00, 0x0A, // packet identifier
+            12,
+            0x01, 0x01, // payload format indicator
+            0x0B, 0x01, 0x0B, 0x80, 0x01, // subscription identifiers
+            0x26, 0x00, 0x00, 0x00, 0x00, // user property
+            b'h', b'i',
+        ]);
+    }
+
+    #[test]
+    fn test_pub_acks() {
+        round_trip(&[0b0100_0000, 4, 0x00, 0x0A, 0x10, 0]);
+        round_trip(&[0b0101_0000, 4, 0x00, 0x0A, 0x00, 0]);
+        round_trip(&[0b0110_0010, 4, 0x00, 0x0A, 0x92, 0]);
+        round_trip(&[0b0111_0000, 8, 0x00, 0x0A, 0x00, 4, 0x1F, 0x00, 0x01, b'x']);
+    }
+
+    #[test]
+    fn test_subscribe() {
+        round_trip(&[
+            0b1000_0010, 15,
+            0x00, 0x0A, // packet identifier
+            2, 0x0B, 0x05, // subscription identifier
+            0x00, 0x03, b'a', b'/', b'#', 0b0010_1110,
+            0x00, 0x01, b'b', 0b0000_0001,
+        ]);
+        round_trip(&[
+            0b1001_0000, 6,
+            0x00, 0x0A, 0, 0x02, 0x01, 0x9E,
+        ]);
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        round_trip(&[
+            0b1010_0010, 10,
+            0x00, 0x0A, 0,
+            0x00, 0x01, b'a', 0x00, 0x02, b'b', b'/',
+        ]);
+        round_trip(&[
+            0b1011_0000, 5,
+            0x00, 0x0A, 0, 0x00, 0x11,
+        ]);
+    }
+
+    #[test]
+    fn test_ping_disconnect_auth() {
+        round_trip(&[0b1100_0000, 0]);
+        round_trip(&[0b1101_0000, 0]);
+        round_trip(&[0b1110_0000, 2, 0x8E, 0]);
+        round_trip(&[
+            0b1111_0000, 10,
+            0x18, 8, 0x15, 0x00, 0x05, b'S', b'C', b'R', b'A', b'M',
+        ]);
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        // remaining length spread over two bytes
+        let mut bytes = vec![0b0011_0000, 0x86, 0x01, 0x00, 0x01, b't', 0];
+        bytes.extend_from_slice(&[0xAB; 130]);
+        round_trip(&bytes);
+
+        let bytes = &[
+            16,
+            0x01, 0x01, // payload format indicator
+            0x0B, 0x01, 0x0B, 0x80, 0x01, // subscription identifiers
+            0x26, 0x00, 0x01, b'k', 0x00, 0x03, b'v', b'a', b'l', // user property
+        ];
+        let (_, property) = mqtt5_property(bytes).unwrap();
+        assert_eq!(property.encoded_len(), bytes.len());
+    }
+
+    #[test]
+    fn test_encode_invalid_length() {
+        let (_, mut packet) = parse(&[0b0011_0000, 6, 0x00, 0x01, b't', 0, b'h', b'i']).unwrap();
+        let topic_name = "a".repeat(65_536);
+        if let Packet::Publish(publish) = &mut packet {
+            publish.variable_header.topic_name = &topic_name;
+        }
+        let mut buf = vec![0xAB];
+        assert_eq!(encode_into(&packet, &mut buf), Err(Error::InvalidLength));
+        assert_eq!(buf, vec![0xAB]);
+    }
+
+    #[test]
+    fn test_encode_invalid_packet() {
+        let subscribe = &[0b1000_0010, 7, 0x00, 0x0A, 0, 0x00, 0x01, b't', 0b0000_0001];
+        for retain_handling in &[3, 4, 17] {
+            let (_, mut packet) = parse(subscribe).unwrap();
+            if let Packet::Subscribe(subscribe) = &mut packet {
+                subscribe.payload[0].1.retain_handling = *retain_handling;
+            }
+            assert_eq!(to_bytes(&packet), Err(Error::MalformedPacket));
+        }
+        let (_, mut packet) = parse(subscribe).unwrap();
+        if let Packet::Subscribe(subscribe) = &mut packet {
+            subscribe.payload[0].1.maximum_qos = Qos::Reserved;
+        }
+        assert_eq!(to_bytes(&packet), Err(Error::InvalidQos(3)));
+
+        let (_, mut packet) = parse(&[0b0011_0010, 6, 0x00, 0x01, b't', 0x00, 0x0A, 0]).unwrap();
+        if let Packet::Publish(publish) = &mut packet {
+            publish.fixed_header.qos = Qos::Reserved;
+        }
+        assert_eq!(to_bytes(&packet), Err(Error::InvalidQos(3)));
+
+        // packets with a zero packet identifier
+        for bytes in &[
+            &[0b0011_0010, 6, 0x00, 0x01, b't', 0x00, 0x0A, 0][..], // publish qos 1
+            &[0b0100_0000, 4, 0x00, 0x0A, 0x00, 0], // puback
+            &[0b0101_0000, 4, 0x00, 0x0A, 0x00, 0], // pubrec
+            &[0b0110_0010, 4, 0x00, 0x0A, 0x00, 0], // pubrel
+            &[0b0111_0000, 4, 0x00, 0x0A, 0x00, 0], // pubcomp
+            subscribe,
+            &[0b1001_0000, 4, 0x00, 0x0A, 0, 0x00], // suback
+            &[0b1010_0010, 6, 0x00, 0x0A, 0, 0x00, 0x01, b't'], // unsubscribe
+            &[0b1011_0000, 4, 0x00, 0x0A, 0, 0x00], // unsuback
+        ] {
+            let (_, mut packet) = parse(bytes).unwrap();
+            match &mut packet {
+                Packet::Publish(publish) => publish.variable_header.packet_id = 0,
+                Packet::PubAck(puback) => puback.variable_header.packet_id = 0,
+                Packet::PubRec(pubrec) => pubrec.variable_header.packet_id = 0,
+                Packet::PubRel(pubrel) => pubrel.variable_header.packet_id = 0,
+                Packet::PubComp(pubcomp) => pubcomp.variable_header.packet_id = 0,
+                Packet::Subscribe(subscribe) => subscribe.variable_header.packet_id = 0,
+                Packet::SubAck(suback) => suback.variable_header.packet_id = 0,
+                Packet::UnSubscribe(unsubscribe) => unsubscribe.variable_header.packet_id = 0,
+                Packet::UnSubAck(unsuback) => unsuback.variable_header.packet_id = 0,
+                packet => panic!("unexpected packet: {:?}", packet),
+            }
+            let mut buf = vec![0xAB];
+            assert_eq!(encode_into(&packet, &mut buf), Err(Error::InvalidPacketId), "{:?}", bytes);
+            assert_eq!(buf, vec![0xAB]);
+        }
+        // qos 0 publish carries no packet identifier
+        let (_, packet) = parse(&[0b0011_0000, 4, 0x00, 0x01, b't', 0]).unwrap();
+        assert!(to_bytes(&packet).is_ok());
+    }
+}
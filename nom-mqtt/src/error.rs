@@ -1,3 +1,6 @@
+use crate::reason_code::{ConnectReasonCode, DisconnectReasonCode, PubAckReasonCode};
+use nom::error::{ContextError, ErrorKind, FromExternalError, ParseError, VerboseErrorKind};
+use nom::Err as NomErr;
 #[allow(unused)]
 use std::fmt;
 
@@ -21,6 +24,10 @@ pub enum Error {
 
     InvalidPacketType(u8),
 
+    InvalidPacketId,
+
+    InvalidSubscriptionId,
+
     MalformedPacket,
 
     Incomplete,
@@ -30,7 +37,7 @@ pub enum Error {
 
 impl From<String> for Error {
     fn from(src: String) -> Error {
-        Error::Other(src.into())
+        Error::Other(src)
     }
 }
 
@@ -54,6 +61,8 @@ impl fmt::Display for Error {
             Error::InvalidString(err) => err.fmt(fmt),
             Error::InvalidPropertyType(err) => err.fmt(fmt),
             Error::InvalidPacketType(_) => "Invalid packet type".fmt(fmt),
+            Error::InvalidPacketId => "Invalid packet identifier".fmt(fmt),
+            Error::InvalidSubscriptionId => "Invalid subscription identifier".fmt(fmt),
             Error::MalformedPacket => "Malformed packet".fmt(fmt),
             Error::Incomplete => "Incomplete Packet".fmt(fmt),
             Error::Other(str) => str.fmt(fmt),
@@ -66,3 +75,217 @@ impl std::convert::From<std::io::Error> for Error {
         Error::MalformedPacket
     }
 }
+
+/// Error returned by the parsers.
+///
+/// Besides nom's trace of where parsing stopped, it keeps the `Error` reported by the
+/// innermost parser that had one, so the caller can answer with the right Reason Code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError<I> {
+    /// Innermost failure first, followed by the contexts it went through
+    pub errors: Vec<(I, VerboseErrorKind)>,
+    pub error: Option<Error>,
+}
+
+impl<I> ParseError<I> for DecodeError<I> {
+    fn from_error_kind(input: I, kind: ErrorKind) -> Self {
+        DecodeError {
+            errors: vec![(input, VerboseErrorKind::Nom(kind))],
+            error: None,
+        }
+    }
+
+    fn append(input: I, kind: ErrorKind, mut other: Self) -> Self {
+        other.errors.push((input, VerboseErrorKind::Nom(kind)));
+        other
+    }
+
+    fn from_char(input: I, c: char) -> Self {
+        DecodeError {
+            errors: vec![(input, VerboseErrorKind::Char(c))],
+            error: None,
+        }
+    }
+}
+
+impl<I> ContextError<I> for DecodeError<I> {
+    fn add_context(input: I, ctx: &'static str, mut other: Self) -> Self {
+        other.errors.push((input, VerboseErrorKind::Context(ctx)));
+        other
+    }
+}
+
+impl<I> FromExternalError<I, Error> for DecodeError<I> {
+    fn from_external_error(input: I, kind: ErrorKind, error: Error) -> Self {
+        DecodeError {
+            errors: vec![(input, VerboseErrorKind::Nom(kind))],
+            error: Some(error),
+        }
+    }
+}
+
+/// A failure without a more specific cause is a Malformed Packet
+impl<I> From<DecodeError<I>> for Error {
+    fn from(err: DecodeError<I>) -> Self {
+        err.error.unwrap_or(Error::MalformedPacket)
+    }
+}
+
+impl<I> From<NomErr<DecodeError<I>>> for Error {
+    fn from(err: NomErr<DecodeError<I>>) -> Self {
+        match err {
+            NomErr::Incomplete(_) => Error::Incomplete,
+            NomErr::Error(err) | NomErr::Failure(err) => err.into(),
+        }
+    }
+}
+
+impl Error {
+    /// Reason Code for the `DISCONNECT` sent when a packet fails to decode after the connection
+    /// is established. `None` for `Incomplete`, which only means more bytes have to be read
+    pub fn disconnect_reason_code(&self) -> Option<DisconnectReasonCode> {
+        match self {
+            Error::MalformedVariableByteInteger
+            | Error::MalformedFixedHeader
+            | Error::InvalidQos(_)
+            | Error::InvalidReasonCode(_)
+            | Error::InvalidLength
+            | Error::InvalidString(_)
+            | Error::InvalidPropertyType(_)
+            | Error::InvalidPacketType(_)
+            | Error::MalformedPacket => Some(DisconnectReasonCode::MalformedPacket),
+            Error::InvalidProtocol(_, _)
+            | Error::InvalidPacketId
+            | Error::InvalidSubscriptionId => Some(DisconnectReasonCode::ProtocolError),
+            Error::Other(_) => Some(DisconnectReasonCode::UnspecifiedError),
+            Error::Incomplete => None,
+        }
+    }
+
+    /// Reason Code for the `CONNACK` sent when the `CONNECT` packet fails to decode.
+    /// `None` for `Incomplete`
+    pub fn connect_reason_code(&self) -> Option<ConnectReasonCode> {
+        match self {
+            Error::MalformedVariableByteInteger
+            | Error::MalformedFixedHeader
+            | Error::InvalidQos(_)
+            | Error::InvalidReasonCode(_)
+            | Error::InvalidLength
+            | Error::InvalidString(_)
+            | Error::InvalidPropertyType(_)
+            | Error::MalformedPacket => Some(ConnectReasonCode::MalformedPacket),
+            Error::InvalidProtocol(_, _) => Some(ConnectReasonCode::UnsupportedProtocolVersion),
+            Error::InvalidPacketType(_) | Error::InvalidPacketId | Error::InvalidSubscriptionId => {
+                Some(ConnectReasonCode::ProtocolError)
+            }
+            Error::Other(_) => Some(ConnectReasonCode::UnspecifiedError),
+            Error::Incomplete => None,
+        }
+    }
+
+    /// Reason Code for the `PUBACK` sent when a `PUBLISH` is refused. `PUBACK` has no Malformed
+    /// Packet or Protocol Error code, so `None` for those: a malformed `PUBLISH` must be answered
+    /// with `DISCONNECT` instead, its Packet Identifier cannot be trusted. `None` for `Incomplete`
+    pub fn puback_reason_code(&self) -> Option<PubAckReasonCode> {
+        match self {
+            Error::Other(_) => Some(PubAckReasonCode::ImplementationSpecificError),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_error {
+    use crate::error::Error;
+    use crate::parse;
+    use crate::reason_code::{ConnectReasonCode, DisconnectReasonCode, PubAckReasonCode};
+
+    #[test]
+    fn test_reason_code_mapping() {
+        let err = Error::InvalidProtocol("MQTT".to_string(), 3);
+        assert_eq!(
+            err.disconnect_reason_code(),
+            Some(DisconnectReasonCode::ProtocolError)
+        );
+        assert_eq!(
+            err.connect_reason_code(),
+            Some(ConnectReasonCode::UnsupportedProtocolVersion)
+        );
+
+        let err = Error::MalformedVariableByteInteger;
+        assert_eq!(
+            err.disconnect_reason_code(),
+            Some(DisconnectReasonCode::MalformedPacket)
+        );
+        assert_eq!(
+            err.connect_reason_code(),
+            Some(ConnectReasonCode::MalformedPacket)
+        );
+        assert_eq!(err.puback_reason_code(), None);
+
+        let err = Error::InvalidSubscriptionId;
+        assert_eq!(
+            err.disconnect_reason_code(),
+            Some(DisconnectReasonCode::ProtocolError)
+        );
+        assert_eq!(err.puback_reason_code(), None);
+
+        // a malformed or protocol error PUBLISH is never acknowledged
+        for err in &[
+            Error::MalformedPacket,
+            Error::MalformedFixedHeader,
+            Error::InvalidQos(3),
+            Error::InvalidString("invalid utf-8".to_string()),
+            Error::InvalidPropertyType("unknown property identifier 0x07".to_string()),
+            Error::InvalidPacketId,
+        ] {
+            assert_eq!(err.puback_reason_code(), None, "{:?}", err);
+        }
+        assert_eq!(
+            Error::Other("quota".to_string()).puback_reason_code(),
+            Some(PubAckReasonCode::ImplementationSpecificError)
+        );
+
+        assert_eq!(Error::Incomplete.disconnect_reason_code(), None);
+        assert_eq!(Error::Incomplete.connect_reason_code(), None);
+        assert_eq!(Error::Incomplete.puback_reason_code(), None);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_parse_reason_code() {
+        // protocol level 4
+        let err = Error::from(parse(&[
+            0b0001_0000, 13,
+            0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04,
+            0b0000_0010, 0x00, 0x10, 0,
+            0x00, 0x00,
+        ]).unwrap_err());
+        assert_eq!(err, Error::InvalidProtocol("MQTT".to_string(), 4));
+        assert_eq!(err.connect_reason_code(), Some(ConnectReasonCode::UnsupportedProtocolVersion));
+
+        // unknown puback reason code
+        let err = Error::from(parse(&[0b0100_0000, 4, 0x00, 0x01, 0x05, 0]).unwrap_err());
+        assert_eq!(err, Error::InvalidReasonCode(0x05));
+        assert_eq!(err.disconnect_reason_code(), Some(DisconnectReasonCode::MalformedPacket));
+
+        // zero packet identifier
+        let err = Error::from(parse(&[0b0100_0000, 4, 0x00, 0x00, 0x00, 0x00]).unwrap_err());
+        assert_eq!(err, Error::InvalidPacketId);
+        assert_eq!(err.disconnect_reason_code(), Some(DisconnectReasonCode::ProtocolError));
+
+        // subscription identifier 0
+        let err = Error::from(parse(&[
+            0b1000_0010, 9,
+            0x00, 0x0A, 2, 0x0B, 0x00, // packet identifier, subscription identifier
+            0x00, 0x01, b't', 0x00,
+        ]).unwrap_err());
+        assert_eq!(err, Error::InvalidSubscriptionId);
+        assert_eq!(err.disconnect_reason_code(), Some(DisconnectReasonCode::ProtocolError));
+
+        // a partial read is not a decode failure
+        let err = Error::from(parse(&[0b0100_0000, 4, 0x00]).unwrap_err());
+        assert_eq!(err, Error::Incomplete);
+        assert_eq!(err.disconnect_reason_code(), None);
+    }
+}
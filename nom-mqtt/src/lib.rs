@@ -1,8 +1,5 @@
-#[allow(unused)]
-#[macro_use]
-extern crate nom;
-
 use crate::bytes::{read_bytes, read_string, read_variable_bytes};
+use crate::error::{DecodeError, Error};
 use crate::packet::{
     Auth, AuthVariableHeader, ConnAck, ConnAckFlags, ConnAckVariableHeader, Connect, ConnectFlags,
     ConnectPayload, ConnectVariableHeader, Disconnect, DisconnectVariableHeader, FixedHeader,
@@ -12,47 +9,185 @@ use crate::packet::{
     Subscribe, SubscribeVariableHeader, SubscriptionOptions, UnSubAck, UnSubAckVariableHeader,
     UnSubscribe, UnSubscribeVariableHeader,
 };
-use nom::branch::alt;
 use nom::bytes::complete::take;
-use nom::combinator::{all_consuming, cond, verify};
-use nom::error::{context, ErrorKind, ParseError, VerboseError};
-use nom::multi::{fold_many1, many1};
+use nom::combinator::{all_consuming, cond, map_res, success, verify};
+use nom::error::{context, ContextError, ErrorKind, FromExternalError, ParseError};
 use nom::number::complete::{be_u16, be_u32, be_u8};
 use nom::sequence::{pair, tuple};
-use nom::{Err as NomErr, InputIter, InputTake, Parser};
+use nom::{Err as NomErr, InputIter, InputTake, Needed, Parser};
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
 type IResult<I, O, E = (I, ErrorKind)> = Result<(I, O), NomErr<E>>;
-type Res<T, U> = IResult<T, U, VerboseError<T>>;
+type Res<T, U> = IResult<T, U, DecodeError<T>>;
 
 pub mod bytes;
+pub mod display;
+pub mod encode;
 pub mod error;
 pub mod packet;
 pub mod reason_code;
 #[cfg(test)]
 mod tests;
 
-pub fn parse(input: &[u8]) -> Res<&[u8], Packet> {
-    all_consuming(alt((
-        connect,
-        connack,
-        publish,
-        puback,
-        pubrec,
-        pubrel,
-        pubcomp,
-        subscribe,
-        suback,
-        unsubscribe,
-        unsuback,
-        ping_req,
-        ping_resp,
-        disconnect,
-        auth,
-    )))(input)
-}
-
-fn auth(input: &[u8]) -> Res<&[u8], Packet> {
+/// Parse one packet from the front of `input` and return the bytes following it.
+///
+/// The packet boundary is found from the fixed header first, so a partial read yields
+/// `Err::Incomplete` with the exact number of bytes still missing, and `input` can be a
+/// socket buffer holding several packets.
+pub fn parse(input: &[u8]) -> Res<&[u8], Packet<'_>> {
+    let (next_input, (packet_type, frame)) = packet_frame(input)?;
+    // the packet type picks the parser, so a failure is reported by the parser of that packet
+    let packet: fn(&[u8]) -> Res<&[u8], Packet<'_>> = match packet_type {
+        PacketType::CONNECT => connect,
+        PacketType::CONNACK => connack,
+        PacketType::PUBLISH => publish,
+        PacketType::PUBACK => puback,
+        PacketType::PUBREC => pubrec,
+        PacketType::PUBREL => pubrel,
+        PacketType::PUBCOMP => pubcomp,
+        PacketType::SUBSCRIBE => subscribe,
+        PacketType::SUBACK => suback,
+        PacketType::UNSUBSCRIBE => unsubscribe,
+        PacketType::UNSUBACK => unsuback,
+        PacketType::PINGREQ => ping_req,
+        PacketType::PINGRESP => ping_resp,
+        PacketType::DISCONNECT => disconnect,
+        PacketType::AUTH => auth,
+    };
+    let (_, packet) = all_consuming(packet)(frame)?;
+    Ok((next_input, packet))
+}
+
+/// Split off a whole packet (fixed header included) without parsing its content.
+///
+/// The first byte and the Remaining Length are checked before the rest of the packet is asked
+/// for, so a reader following `Needed` does not buffer a packet that is bound to fail.
+fn packet_frame(input: &[u8]) -> Res<&[u8], (PacketType, &[u8])> {
+    let fixed_header_error = |err| {
+        NomErr::Error(DecodeError::add_context(
+            input,
+            "fixed header",
+            DecodeError::from_external_error(input, ErrorKind::Verify, err),
+        ))
+    };
+    let packet_type = match input.first() {
+        Some(byte) => packet_type_and_qos(*byte).map_err(fixed_header_error)?.0,
+        None => return Err(NomErr::Incomplete(Needed::new(2))),
+    };
+    let (header_length, remaining_length) = frame_length(input)?;
+    // PINGREQ and PINGRESP have neither variable header nor payload
+    if remaining_length != 0
+        && (packet_type == PacketType::PINGREQ || packet_type == PacketType::PINGRESP)
+    {
+        return Err(fixed_header_error(Error::MalformedFixedHeader));
+    }
+    let packet_length = header_length + remaining_length;
+    if input.len() < packet_length {
+        return Err(NomErr::Incomplete(Needed::new(packet_length - input.len())));
+    }
+    let (next_input, frame) = input.take_split(packet_length);
+    Ok((next_input, (packet_type, frame)))
+}
+
+/// Read the packet type and the QoS from the first byte of the fixed header. The flags of
+/// every packet type but `PUBLISH` are fixed: 0b0010 for `PUBREL`, `SUBSCRIBE` and
+/// `UNSUBSCRIBE`, 0 for the others
+fn packet_type_and_qos(byte: u8) -> Result<(PacketType, Qos), Error> {
+    let packet_type = PacketType::try_from(byte >> 4)?;
+    let qos = Qos::try_from((byte >> 1) & 0x03)?;
+    let flags = byte & 0x0F;
+    let valid_flags = match packet_type {
+        PacketType::PUBLISH => true,
+        PacketType::PUBREL | PacketType::SUBSCRIBE | PacketType::UNSUBSCRIBE => flags == 0b0010,
+        _ => flags == 0,
+    };
+    if !valid_flags {
+        return Err(Error::MalformedFixedHeader);
+    }
+    Ok((packet_type, qos))
+}
+
+/// Read the length of the fixed header and the Remaining Length, which may be all there is of the packet yet
+fn frame_length(input: &[u8]) -> Result<(usize, usize), NomErr<DecodeError<&[u8]>>> {
+    let mut remaining_length = 0;
+    // the Remaining Length is a Variable Byte Integer of at most 4 bytes following the first byte
+    for position in 1..=4 {
+        let byte = match input.get(position) {
+            Some(byte) => *byte,
+            None => return Err(NomErr::Incomplete(Needed::new(position + 1 - input.len()))),
+        };
+        remaining_length += (byte as usize & 0x7F) << (7 * (position - 1));
+        if byte & 0x80 == 0 {
+            return Ok((position + 1, remaining_length));
+        }
+    }
+    Err(NomErr::Error(DecodeError::add_context(
+        input,
+        "packet frame",
+        DecodeError::from_external_error(
+            input,
+            ErrorKind::TooLarge,
+            Error::MalformedVariableByteInteger,
+        ),
+    )))
+}
+
+/// Longest Protocol Name `connect_preamble` reads to report it, "MQIsdp" of MQTT 3.1
+const MAX_PROTOCOL_NAME_LENGTH: usize = 6;
+
+/// Check the start of a `CONNECT` before the whole packet has arrived.
+///
+/// Only the fixed header, Protocol Name, Protocol Version and Connect Flags are read, so a
+/// client with the wrong protocol version can be answered with `UnsupportedProtocolVersion`
+/// (see `Error::connect_reason_code`) without buffering the Remaining Length it
+/// announced. Returns `Ok(None)` while those bytes are still missing. A Protocol Name longer
+/// than 6 bytes is refused at once with an empty name and version 0, as neither is read.
+/// Connect Flags with the reserved bit set, or with Will QoS or Will Retain but no Will Flag,
+/// are refused as a Malformed Packet.
+pub fn connect_preamble(input: &[u8]) -> Result<Option<(FixedHeader, ConnectFlags)>, Error> {
+    if let Some(byte) = input.first() {
+        if PacketType::try_from(byte >> 4)? != PacketType::CONNECT {
+            return Err(Error::InvalidPacketType(byte >> 4));
+        }
+    }
+    let header_length = match frame_length(input) {
+        Ok((header_length, _)) => header_length,
+        Err(NomErr::Incomplete(_)) => return Ok(None),
+        Err(_) => return Err(Error::MalformedVariableByteInteger),
+    };
+    let (_, fixed_header) = fixed_header(input).map_err(|_| Error::MalformedFixedHeader)?;
+    let variable_header = &input[header_length..];
+    let name_length = match variable_header.get(..2) {
+        Some(name_length) => u16::from_be_bytes([name_length[0], name_length[1]]) as usize,
+        None => return Ok(None),
+    };
+    // only "MQTT" is accepted, a longer name is refused before it is buffered
+    if name_length > MAX_PROTOCOL_NAME_LENGTH {
+        return Err(Error::InvalidProtocol(String::new(), 0));
+    }
+    // Protocol Name, Protocol Version and Connect Flags
+    let preamble_length = 2 + name_length + 2;
+    if preamble_length > fixed_header.remaining_length {
+        return Err(Error::MalformedPacket);
+    }
+    if variable_header.len() < preamble_length {
+        return Ok(None);
+    }
+    let name = &variable_header[2..2 + name_length];
+    let level = variable_header[2 + name_length];
+    if name != b"MQTT" || level != 5 {
+        return Err(Error::InvalidProtocol(
+            String::from_utf8_lossy(name).into_owned(),
+            level,
+        ));
+    }
+    let (_, connect_flags) =
+        connect_flag(&variable_header[3 + name_length..]).map_err(|_| Error::MalformedPacket)?;
+    Ok(Some((fixed_header, connect_flags)))
+}
+
+fn auth(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "auth",
         verify(
@@ -62,7 +197,7 @@ fn auth(input: &[u8]) -> Res<&[u8], Packet> {
                 }),
                 auth_variable_header,
             ),
-            |(_, _, payload)| payload.len() == 0,
+            |(_, _, payload)| payload.is_empty(),
         ),
     )(input)
     .map(|(next_input, (fixed_header, variable_header, _))| {
@@ -76,21 +211,23 @@ fn auth(input: &[u8]) -> Res<&[u8], Packet> {
     })
 }
 
-fn auth_variable_header(input: &[u8]) -> Res<&[u8], AuthVariableHeader> {
-    context("auth variable header", pair(be_u8, mqtt5_property))(input).map(
-        |(next_input, (reason_code, auth_property))| {
-            (
-                next_input,
-                AuthVariableHeader {
-                    auth_reason_code: reason_code.into(),
-                    auth_property,
-                },
-            )
-        },
-    )
+fn auth_variable_header(input: &[u8]) -> Res<&[u8], AuthVariableHeader<'_>> {
+    context(
+        "auth variable header",
+        pair(optional_reason_code, optional_mqtt5_property),
+    )(input)
+    .map(|(next_input, (reason_code, auth_property))| {
+        (
+            next_input,
+            AuthVariableHeader {
+                auth_reason_code: reason_code,
+                auth_property,
+            },
+        )
+    })
 }
 
-fn connack(input: &[u8]) -> Res<&[u8], Packet> {
+fn connack(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "connack",
         verify(
@@ -100,7 +237,7 @@ fn connack(input: &[u8]) -> Res<&[u8], Packet> {
                 }),
                 connack_variable_header,
             ),
-            |(_, _, payload)| payload.len() == 0,
+            |(_, _, payload)| payload.is_empty(),
         ),
     )(input)
     .map(|(next_input, (fixed_header, variable_header, _))| {
@@ -114,27 +251,40 @@ fn connack(input: &[u8]) -> Res<&[u8], Packet> {
     })
 }
 
-fn connack_variable_header(input: &[u8]) -> Res<&[u8], ConnAckVariableHeader> {
+fn connack_variable_header(input: &[u8]) -> Res<&[u8], ConnAckVariableHeader<'_>> {
     context(
         "connack variable header",
-        tuple((be_u8, be_u8, mqtt5_property)),
+        tuple((connack_flag, reason_code, mqtt5_property)),
     )(input)
-    .map(|(next_input, (flag, reason_code, connack_property))| {
-        (
-            next_input,
-            ConnAckVariableHeader {
-                connack_flags: ConnAckFlags {
-                    // fault tolerance
-                    session_present: (flag & 0b1111_1111) > 1,
+    .map(
+        |(next_input, (connack_flags, reason_code, connack_property))| {
+            (
+                next_input,
+                ConnAckVariableHeader {
+                    connack_flags,
+                    connect_reason_code: reason_code,
+                    connack_property,
                 },
-                connect_reason_code: reason_code.into(),
-                connack_property,
-            },
-        )
-    })
+            )
+        },
+    )
+}
+
+/// Bits 7-1 of the Connect Acknowledge Flags are reserved and must be 0
+fn connack_flag(input: &[u8]) -> Res<&[u8], ConnAckFlags> {
+    context("connack flag", verify(be_u8, |flag| flag & 0xFE == 0))(input).map(
+        |(next_input, flag)| {
+            (
+                next_input,
+                ConnAckFlags {
+                    session_present: flag & 0x01 == 1,
+                },
+            )
+        },
+    )
 }
 
-fn disconnect(input: &[u8]) -> Res<&[u8], Packet> {
+fn disconnect(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "disconnect",
         verify(
@@ -144,7 +294,7 @@ fn disconnect(input: &[u8]) -> Res<&[u8], Packet> {
                 }),
                 disconnect_variable_header,
             ),
-            |(_, _, payload)| payload.len() == 0,
+            |(_, _, payload)| payload.is_empty(),
         ),
     )(input)
     .map(|(next_input, (fixed_header, variable_header, _))| {
@@ -158,21 +308,23 @@ fn disconnect(input: &[u8]) -> Res<&[u8], Packet> {
     })
 }
 
-fn disconnect_variable_header(input: &[u8]) -> Res<&[u8], DisconnectVariableHeader> {
-    context("disconnect vairable header", pair(be_u8, mqtt5_property))(input).map(
-        |(next_input, (reason_code, disconnect_property))| {
-            (
-                next_input,
-                DisconnectVariableHeader {
-                    disconnect_reason_code: reason_code.into(),
-                    disconnect_property,
-                },
-            )
-        },
-    )
+fn disconnect_variable_header(input: &[u8]) -> Res<&[u8], DisconnectVariableHeader<'_>> {
+    context(
+        "disconnect vairable header",
+        pair(optional_reason_code, optional_mqtt5_property),
+    )(input)
+    .map(|(next_input, (reason_code, disconnect_property))| {
+        (
+            next_input,
+            DisconnectVariableHeader {
+                disconnect_reason_code: reason_code,
+                disconnect_property,
+            },
+        )
+    })
 }
 
-fn ping_req(input: &[u8]) -> Res<&[u8], Packet> {
+fn ping_req(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "pingresp",
         verify(fixed_header, |fixed_header| {
@@ -182,7 +334,7 @@ fn ping_req(input: &[u8]) -> Res<&[u8], Packet> {
     .map(|(next_input, fixed_header)| (next_input, Packet::PingReq(PingReq { fixed_header })))
 }
 
-fn ping_resp(input: &[u8]) -> Res<&[u8], Packet> {
+fn ping_resp(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "pingresp",
         verify(fixed_header, |fixed_header| {
@@ -192,7 +344,7 @@ fn ping_resp(input: &[u8]) -> Res<&[u8], Packet> {
     .map(|(next_input, fixed_header)| (next_input, Packet::PingResp(PingResp { fixed_header })))
 }
 
-fn puback(input: &[u8]) -> Res<&[u8], Packet> {
+fn puback(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "puback",
         verify(
@@ -202,7 +354,7 @@ fn puback(input: &[u8]) -> Res<&[u8], Packet> {
                 }),
                 puback_variable_header,
             ),
-            |(_, _, payload)| payload.len() == 0,
+            |(_, _, payload)| payload.is_empty(),
         ),
     )(input)
     .map(|(next_input, (fixed_header, variable_header, _))| {
@@ -216,24 +368,24 @@ fn puback(input: &[u8]) -> Res<&[u8], Packet> {
     })
 }
 
-fn puback_variable_header(input: &[u8]) -> Res<&[u8], PubAckVariableHeader> {
+fn puback_variable_header(input: &[u8]) -> Res<&[u8], PubAckVariableHeader<'_>> {
     context(
         "puback variable header",
-        tuple((be_u16, be_u8, mqtt5_property)),
+        tuple((packet_id, optional_reason_code, optional_mqtt5_property)),
     )(input)
     .map(|(next_input, (packet_id, reason_code, puback_property))| {
         (
             next_input,
             PubAckVariableHeader {
                 packet_id,
-                puback_reason_code: reason_code.into(),
+                puback_reason_code: reason_code,
                 puback_property,
             },
         )
     })
 }
 
-fn pubcomp(input: &[u8]) -> Res<&[u8], Packet> {
+fn pubcomp(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "pubcomp",
         verify(
@@ -243,7 +395,7 @@ fn pubcomp(input: &[u8]) -> Res<&[u8], Packet> {
                 }),
                 pubcomp_variable_header,
             ),
-            |(_, _, payload)| payload.len() == 0,
+            |(_, _, payload)| payload.is_empty(),
         ),
     )(input)
     .map(|(next_input, (fixed_header, variable_header, _))| {
@@ -257,66 +409,70 @@ fn pubcomp(input: &[u8]) -> Res<&[u8], Packet> {
     })
 }
 
-fn pubcomp_variable_header(input: &[u8]) -> Res<&[u8], PubCompVariableHeader> {
+fn pubcomp_variable_header(input: &[u8]) -> Res<&[u8], PubCompVariableHeader<'_>> {
     context(
         "pubcomp variable header",
-        tuple((be_u16, be_u8, mqtt5_property)),
+        tuple((packet_id, optional_reason_code, optional_mqtt5_property)),
     )(input)
     .map(|(next_input, (packet_id, reason_code, pubcomp_property))| {
         (
             next_input,
             PubCompVariableHeader {
                 packet_id,
-                pubcomp_reason_code: reason_code.into(),
+                pubcomp_reason_code: reason_code,
                 pubcomp_property,
             },
         )
     })
 }
 
-fn publish(input: &[u8]) -> Res<&[u8], Packet> {
-    context(
-        "publish",
-        verify(
-            map_fixed_header(
-                verify(fixed_header, |fixed_header| {
-                    fixed_header.packet_type == PacketType::PUBLISH
-                }),
-                publish_variable_header,
-            ),
-            |(_, _, payload)| payload.len() == 0,
-        ),
-    )(input)
-    .map(|(next_input, (fixed_header, variable_header, payload))| {
-        (
+fn publish(input: &[u8]) -> Res<&[u8], Packet<'_>> {
+    context("publish", |input| {
+        let (input, fixed_header) = verify(fixed_header, |fixed_header| {
+            fixed_header.packet_type == PacketType::PUBLISH
+        })(input)?;
+        let (next_input, variable_header_and_payload) = take(fixed_header.remaining_length)(input)?;
+        let (payload, variable_header) =
+            publish_variable_header(fixed_header.qos)(variable_header_and_payload)?;
+        Ok((
             next_input,
             Packet::Publish(Publish {
                 fixed_header,
                 variable_header,
                 payload,
             }),
-        )
-    })
+        ))
+    })(input)
 }
 
-fn publish_variable_header(input: &[u8]) -> Res<&[u8], PublishVariableHeader> {
-    context(
-        "publish variable header",
-        tuple((read_string, be_u16, mqtt5_property)),
-    )(input)
-    .map(|(next_input, (topic_name, packet_id, publish_property))| {
-        (
-            next_input,
-            PublishVariableHeader {
-                topic_name,
-                packet_id,
-                publish_property,
-            },
-        )
-    })
+/// The Packet Identifier field is only present in `PUBLISH` packets where the QoS level is 1 or 2,
+/// it is left as 0 otherwise
+fn publish_variable_header<'a>(
+    qos: Qos,
+) -> impl FnMut(&'a [u8]) -> Res<&'a [u8], PublishVariableHeader<'a>> {
+    move |input| {
+        context(
+            "publish variable header",
+            tuple((
+                read_string,
+                cond(qos != Qos::AtMostOnce, packet_id),
+                mqtt5_property,
+            )),
+        )(input)
+        .map(|(next_input, (topic_name, packet_id, publish_property))| {
+            (
+                next_input,
+                PublishVariableHeader {
+                    topic_name,
+                    packet_id: packet_id.unwrap_or_default(),
+                    publish_property,
+                },
+            )
+        })
+    }
 }
 
-fn pubrec(input: &[u8]) -> Res<&[u8], Packet> {
+fn pubrec(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "pubrec",
         verify(
@@ -326,7 +482,7 @@ fn pubrec(input: &[u8]) -> Res<&[u8], Packet> {
                 }),
                 pubrec_variable_header,
             ),
-            |(_, _, payload)| payload.len() == 0,
+            |(_, _, payload)| payload.is_empty(),
         ),
     )(input)
     .map(|(next_input, (fixed_header, variable_header, _))| {
@@ -340,24 +496,24 @@ fn pubrec(input: &[u8]) -> Res<&[u8], Packet> {
     })
 }
 
-fn pubrec_variable_header(input: &[u8]) -> Res<&[u8], PubRecVariableHeader> {
+fn pubrec_variable_header(input: &[u8]) -> Res<&[u8], PubRecVariableHeader<'_>> {
     context(
         "pubrec variable header",
-        tuple((be_u16, be_u8, mqtt5_property)),
+        tuple((packet_id, optional_reason_code, optional_mqtt5_property)),
     )(input)
     .map(|(next_input, (packet_id, reason_code, pubrec_property))| {
         (
             next_input,
             PubRecVariableHeader {
                 packet_id,
-                pubrec_reason_code: reason_code.into(),
+                pubrec_reason_code: reason_code,
                 pubrec_property,
             },
         )
     })
 }
 
-fn pubrel(input: &[u8]) -> Res<&[u8], Packet> {
+fn pubrel(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "pubrel",
         verify(
@@ -367,7 +523,7 @@ fn pubrel(input: &[u8]) -> Res<&[u8], Packet> {
                 }),
                 pubrel_variable_header,
             ),
-            |(_, _, payload)| payload.len() == 0,
+            |(_, _, payload)| payload.is_empty(),
         ),
     )(input)
     .map(|(next_input, (fixed_header, variable_header, _))| {
@@ -381,24 +537,24 @@ fn pubrel(input: &[u8]) -> Res<&[u8], Packet> {
     })
 }
 
-fn pubrel_variable_header(input: &[u8]) -> Res<&[u8], PubRelVariableHeader> {
+fn pubrel_variable_header(input: &[u8]) -> Res<&[u8], PubRelVariableHeader<'_>> {
     context(
         "pubrel variable header",
-        tuple((be_u16, be_u8, mqtt5_property)),
+        tuple((packet_id, optional_reason_code, optional_mqtt5_property)),
     )(input)
     .map(|(next_input, (packet_id, reason_code, pubrel_property))| {
         (
             next_input,
             PubRelVariableHeader {
                 packet_id,
-                pubrel_reason_code: reason_code.into(),
+                pubrel_reason_code: reason_code,
                 pubrel_property,
             },
         )
     })
 }
 
-fn suback(input: &[u8]) -> Res<&[u8], Packet> {
+fn suback(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "suback",
         map_fixed_header(
@@ -409,14 +565,7 @@ fn suback(input: &[u8]) -> Res<&[u8], Packet> {
         ),
     )(input)
     .and_then(|(next_input, (fixed_header, variable_header, payloads))| {
-        let (_, payload) = fold_many1(
-            be_u8,
-            Vec::with_capacity(payloads.len()),
-            |mut acc, item| {
-                acc.push(item.into());
-                acc
-            },
-        )(payloads)?;
+        let (_, payload) = many1_complete(reason_code)(payloads)?;
         Ok((
             next_input,
             Packet::SubAck(SubAck {
@@ -428,8 +577,8 @@ fn suback(input: &[u8]) -> Res<&[u8], Packet> {
     })
 }
 
-fn suback_variable_header(input: &[u8]) -> Res<&[u8], SubAckVariableHeader> {
-    context("suback variable header", pair(be_u16, mqtt5_property))(input).map(
+fn suback_variable_header(input: &[u8]) -> Res<&[u8], SubAckVariableHeader<'_>> {
+    context("suback variable header", pair(packet_id, mqtt5_property))(input).map(
         |(next_input, (packet_id, suback_property))| {
             (
                 next_input,
@@ -442,7 +591,7 @@ fn suback_variable_header(input: &[u8]) -> Res<&[u8], SubAckVariableHeader> {
     )
 }
 
-fn subscribe(input: &[u8]) -> Res<&[u8], Packet> {
+fn subscribe(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "subscribe",
         map_fixed_header(
@@ -453,7 +602,7 @@ fn subscribe(input: &[u8]) -> Res<&[u8], Packet> {
         ),
     )(input)
     .and_then(|(next_input, (fixed_header, variable_header, payloads))| {
-        let (_, payload) = many1(pair(read_string, subscription_options))(payloads)?;
+        let (_, payload) = many1_complete(pair(read_string, subscription_options))(payloads)?;
         Ok((
             next_input,
             Packet::Subscribe(Subscribe {
@@ -466,21 +615,27 @@ fn subscribe(input: &[u8]) -> Res<&[u8], Packet> {
 }
 
 fn subscription_options(input: &[u8]) -> Res<&[u8], SubscriptionOptions> {
-    context("subscription options", be_u8)(input).map(|(next_input, option)| {
-        (
-            next_input,
-            SubscriptionOptions {
-                maximum_qos: (option & 0b0000_0011).into(),
-                no_local: (option >> 2) & 0x01 == 1,
-                retain_as_published: (option >> 3) & 0x01 == 1,
-                retain_handling: (option >> 4) & 0x03,
+    // bits 6 and 7 are reserved, and Retain Handling must not be 3
+    context(
+        "subscription options",
+        map_res(
+            verify(be_u8, |option| {
+                option & 0b1100_0000 == 0 && (option >> 4) & 0x03 != 3
+            }),
+            |option| -> Result<_, Error> {
+                Ok(SubscriptionOptions {
+                    maximum_qos: Qos::try_from(option & 0b0000_0011)?,
+                    no_local: (option >> 2) & 0x01 == 1,
+                    retain_as_published: (option >> 3) & 0x01 == 1,
+                    retain_handling: (option >> 4) & 0x03,
+                })
             },
-        )
-    })
+        ),
+    )(input)
 }
 
-fn subscribe_variable_header(input: &[u8]) -> Res<&[u8], SubscribeVariableHeader> {
-    context("subscribe variable header", pair(be_u16, mqtt5_property))(input).map(
+fn subscribe_variable_header(input: &[u8]) -> Res<&[u8], SubscribeVariableHeader<'_>> {
+    context("subscribe variable header", pair(packet_id, mqtt5_property))(input).map(
         |(next_input, (packet_id, subscribe_property))| {
             (
                 next_input,
@@ -493,7 +648,7 @@ fn subscribe_variable_header(input: &[u8]) -> Res<&[u8], SubscribeVariableHeader
     )
 }
 
-fn unsuback(input: &[u8]) -> Res<&[u8], Packet> {
+fn unsuback(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "unsuback",
         map_fixed_header(
@@ -504,14 +659,7 @@ fn unsuback(input: &[u8]) -> Res<&[u8], Packet> {
         ),
     )(input)
     .and_then(|(next_input, (fixed_header, variable_header, payloads))| {
-        let (_, payload) = fold_many1(
-            be_u8,
-            Vec::with_capacity(payloads.len()),
-            |mut acc, item| {
-                acc.push(item.into());
-                acc
-            },
-        )(payloads)?;
+        let (_, payload) = many1_complete(reason_code)(payloads)?;
         Ok((
             next_input,
             Packet::UnSubAck(UnSubAck {
@@ -523,8 +671,8 @@ fn unsuback(input: &[u8]) -> Res<&[u8], Packet> {
     })
 }
 
-fn unsuback_variable_header(input: &[u8]) -> Res<&[u8], UnSubAckVariableHeader> {
-    context("unsuback variable header", pair(be_u16, mqtt5_property))(input).map(
+fn unsuback_variable_header(input: &[u8]) -> Res<&[u8], UnSubAckVariableHeader<'_>> {
+    context("unsuback variable header", pair(packet_id, mqtt5_property))(input).map(
         |(next_input, (packet_id, unsuback_property))| {
             (
                 next_input,
@@ -537,7 +685,7 @@ fn unsuback_variable_header(input: &[u8]) -> Res<&[u8], UnSubAckVariableHeader>
     )
 }
 
-fn unsubscribe(input: &[u8]) -> Res<&[u8], Packet> {
+fn unsubscribe(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "unsubscribe",
         map_fixed_header(
@@ -548,7 +696,7 @@ fn unsubscribe(input: &[u8]) -> Res<&[u8], Packet> {
         ),
     )(input)
     .and_then(|(next_input, (fixed_header, variable_header, payloads))| {
-        let (_, payload) = many1(read_string)(payloads)?;
+        let (_, payload) = many1_complete(read_string)(payloads)?;
         Ok((
             next_input,
             Packet::UnSubscribe(UnSubscribe {
@@ -560,21 +708,23 @@ fn unsubscribe(input: &[u8]) -> Res<&[u8], Packet> {
     })
 }
 
-fn unsubscribe_variable_header(input: &[u8]) -> Res<&[u8], UnSubscribeVariableHeader> {
-    context("unsubscribe variable header", pair(be_u16, mqtt5_property))(input).map(
-        |(next_input, (packet_id, unsubscribe_property))| {
-            (
-                next_input,
-                UnSubscribeVariableHeader {
-                    packet_id,
-                    unsubscribe_property,
-                },
-            )
-        },
-    )
+fn unsubscribe_variable_header(input: &[u8]) -> Res<&[u8], UnSubscribeVariableHeader<'_>> {
+    context(
+        "unsubscribe variable header",
+        pair(packet_id, mqtt5_property),
+    )(input)
+    .map(|(next_input, (packet_id, unsubscribe_property))| {
+        (
+            next_input,
+            UnSubscribeVariableHeader {
+                packet_id,
+                unsubscribe_property,
+            },
+        )
+    })
 }
 
-fn connect(input: &[u8]) -> Res<&[u8], Packet> {
+fn connect(input: &[u8]) -> Res<&[u8], Packet<'_>> {
     context(
         "connect",
         map_fixed_header(
@@ -616,23 +766,29 @@ fn connect(input: &[u8]) -> Res<&[u8], Packet> {
     })
 }
 
+/// Bit 0 is reserved and must be 0, and Will QoS and Will Retain must be 0 when the Will Flag is 0
 fn connect_flag(input: &[u8]) -> Res<&[u8], ConnectFlags> {
-    context("connect flag", be_u8)(input).map(|(next_input, flag)| {
-        (
-            next_input,
-            ConnectFlags {
-                clean_start: (flag >> 1) & 0x01 > 0,
-                will_flag: (flag >> 2) & 0x01 > 0,
-                will_qos: ((flag >> 3) & 0x03).into(),
-                will_retain: (flag >> 5) & 0x01 > 0,
-                password_flag: (flag >> 6) & 0x01 > 0,
-                username_flag: (flag >> 7) & 0x01 > 0,
+    context(
+        "connect flag",
+        map_res(
+            verify(be_u8, |flag| {
+                flag & 0x01 == 0 && (flag & 0b0000_0100 != 0 || flag & 0b0011_1000 == 0)
+            }),
+            |flag| -> Result<_, Error> {
+                Ok(ConnectFlags {
+                    clean_start: (flag >> 1) & 0x01 > 0,
+                    will_flag: (flag >> 2) & 0x01 > 0,
+                    will_qos: Qos::try_from((flag >> 3) & 0x03)?,
+                    will_retain: (flag >> 5) & 0x01 > 0,
+                    password_flag: (flag >> 6) & 0x01 > 0,
+                    username_flag: (flag >> 7) & 0x01 > 0,
+                })
             },
-        )
-    })
+        ),
+    )(input)
 }
 
-fn connect_variable_header(input: &[u8]) -> Res<&[u8], ConnectVariableHeader> {
+fn connect_variable_header(input: &[u8]) -> Res<&[u8], ConnectVariableHeader<'_>> {
     context(
         "connect variable header",
         tuple((protocol, connect_flag, be_u16, mqtt5_property)),
@@ -653,12 +809,15 @@ fn connect_variable_header(input: &[u8]) -> Res<&[u8], ConnectVariableHeader> {
 }
 
 fn protocol(input: &[u8]) -> Res<&[u8], Protocol> {
-    context("protocol", pair(read_string, be_u8))(input).and_then(|(next_input, (name, level))| {
-        if name == "MQTT" && level == 5u8 {
-            return Ok((next_input, Protocol::MQTT5));
-        }
-        return Err(NomErr::Error(VerboseError { errors: vec![] }));
-    })
+    context(
+        "protocol",
+        map_res(pair(read_string, be_u8), |(name, level)| {
+            if name == "MQTT" && level == 5u8 {
+                return Ok(Protocol::MQTT5);
+            }
+            Err(Error::InvalidProtocol(name.to_string(), level))
+        }),
+    )(input)
 }
 
 pub fn map_fixed_header<I: Clone + InputIter + InputTake, O, E: ParseError<I>, F, G>(
@@ -678,14 +837,23 @@ where
 }
 
 fn fixed_header(input: &[u8]) -> Res<&[u8], FixedHeader> {
-    context("fixed header", pair(be_u8, read_variable_bytes))(input).map(
-        |(next_input, (fixed_header_byte, (remaining_length, _)))| {
+    context(
+        "fixed header",
+        pair(
+            map_res(be_u8, |byte| {
+                packet_type_and_qos(byte).map(|(packet_type, qos)| (packet_type, qos, byte))
+            }),
+            read_variable_bytes,
+        ),
+    )(input)
+    .map(
+        |(next_input, ((packet_type, qos, fixed_header_byte), (remaining_length, _)))| {
             (
                 next_input,
                 FixedHeader {
-                    packet_type: (fixed_header_byte >> 4).into(),
+                    packet_type,
                     dup: (fixed_header_byte >> 3) & 0x01 == 1,
-                    qos: Qos::from((fixed_header_byte >> 1) & 0x03),
+                    qos,
                     retain: fixed_header_byte & 0x01 == 1,
                     remaining_length,
                 },
@@ -694,240 +862,311 @@ fn fixed_header(input: &[u8]) -> Res<&[u8], FixedHeader> {
     )
 }
 
-fn mqtt5_property(input: &[u8]) -> Res<&[u8], Mqtt5Property> {
-    context("mqtt5 property", read_variable_bytes)(input).and_then(
-        |(input, (property_length, _))| {
-            let (next_input, property_input) = take(property_length)(input)?;
-            let (_, (mut properties, subscription_identifiers, user_properties)) =
-                fold_many1(
-                    property_value,
-                    (HashMap::new(), Vec::new(), Vec::new()),
-                    |(mut properties, mut subscription_identifiers, mut user_properties),
-                     (property_id, property_value)| {
-                        if property_id == 0x0B {
-                            subscription_identifiers.push(property_value);
-                        } else if property_id == 0x26 {
-                            user_properties.push(property_value);
-                        } else {
-                            properties.insert(property_id as u32, property_value);
-                        }
-                        (properties, subscription_identifiers, user_properties)
-                    },
-                )(property_input)?;
-            if subscription_identifiers.len() > 0 {
-                properties.insert(0x0B, PropertyValue::Multiple(subscription_identifiers));
-            }
-            if user_properties.len() > 0 {
-                properties.insert(0x26, PropertyValue::Multiple(user_properties));
+/// A Packet Identifier of 0 is a Protocol Error
+fn packet_id(input: &[u8]) -> Res<&[u8], u16> {
+    context(
+        "packet identifier",
+        map_res(be_u16, |packet_id| match packet_id {
+            0 => Err(Error::InvalidPacketId),
+            packet_id => Ok(packet_id),
+        }),
+    )(input)
+}
+
+/// Like `all_consuming(many1(..))`, but an element that fails to parse fails the whole
+/// repetition with its own error instead of ending it
+fn many1_complete<'a, O, F>(mut parser: F) -> impl FnMut(&'a [u8]) -> Res<&'a [u8], Vec<O>>
+where
+    F: Parser<&'a [u8], O, DecodeError<&'a [u8]>>,
+{
+    move |mut input: &'a [u8]| {
+        if input.is_empty() {
+            return Err(NomErr::Error(DecodeError::from_error_kind(
+                input,
+                ErrorKind::Many1,
+            )));
+        }
+        let mut items = Vec::new();
+        while !input.is_empty() {
+            let (remain, item) = parser.parse(input)?;
+            items.push(item);
+            input = remain;
+        }
+        Ok((input, items))
+    }
+}
+
+fn reason_code<T>(input: &[u8]) -> Res<&[u8], T>
+where
+    T: TryFrom<u8, Error = Error>,
+{
+    context("reason code", map_res(be_u8, T::try_from))(input)
+}
+
+/// The Reason Code of `PUBACK`, `PUBREC`, `PUBREL`, `PUBCOMP`, `DISCONNECT` and `AUTH` can be
+/// left out when it is 0x00, it is the last field then
+fn optional_reason_code<T>(input: &[u8]) -> Res<&[u8], T>
+where
+    T: TryFrom<u8, Error = Error>,
+{
+    if input.is_empty() {
+        return context("reason code", map_res(success(0x00), T::try_from))(input);
+    }
+    reason_code(input)
+}
+
+/// The Properties following an optional Reason Code can be left out when there are none,
+/// Property Length included
+fn optional_mqtt5_property(input: &[u8]) -> Res<&[u8], Mqtt5Property<'_>> {
+    if input.is_empty() {
+        return Ok((input, Mqtt5Property::new()));
+    }
+    mqtt5_property(input)
+}
+
+fn mqtt5_property(input: &[u8]) -> Res<&[u8], Mqtt5Property<'_>> {
+    context("mqtt5 property", |input| {
+        let (input, (property_length, _)) = read_variable_bytes(input)?;
+        let (next_input, mut property_input) = take(property_length)(input)?;
+        let mut properties = HashMap::new();
+        let mut subscription_identifiers = Vec::new();
+        let mut user_properties = Vec::new();
+        // every property must be consumed, an unknown or truncated one fails the whole packet
+        while !property_input.is_empty() {
+            let (remain, (property_id, property_value)) = property_value(property_input)?;
+            if property_id == 0x0B {
+                subscription_identifiers.push(property_value);
+            } else if property_id == 0x26 {
+                if let PropertyValue::StringPair(name, value) = property_value {
+                    user_properties.push((name, value));
+                }
+            } else {
+                properties.insert(property_id as u32, property_value);
             }
-            Ok((
-                next_input,
-                Mqtt5Property {
-                    property_length,
-                    properties,
-                },
-            ))
-        },
-    )
+            property_input = remain;
+        }
+        if !subscription_identifiers.is_empty() {
+            properties.insert(0x0B, PropertyValue::Multiple(subscription_identifiers));
+        }
+        Ok((
+            next_input,
+            Mqtt5Property {
+                property_length,
+                properties,
+                user_properties,
+            },
+        ))
+    })(input)
 }
 
-fn property_value(input: &[u8]) -> Res<&[u8], (usize, PropertyValue)> {
+fn property_value(input: &[u8]) -> Res<&[u8], (usize, PropertyValue<'_>)> {
     // Although the Property Identifier is defined as a Variable Byte Integer,
     // in this version of the specification all of the Property Identifiers are one byte long.
-    context("property value", be_u8)(input).and_then(|(input, property_id)| {
-        return match property_id {
-            0x01 => context("payload format indicator", be_u8)(input).map(
-                |(input, payload_format_indicator)| {
-                    (
-                        input,
-                        (
-                            0x01,
-                            PropertyValue::Bit(payload_format_indicator & 0x01 == 1),
-                        ),
-                    )
-                },
-            ),
-            0x02 => context("message expiry interval", be_u32)(input).map(
-                |(input, message_expiry_interval)| {
-                    (
-                        input,
-                        (
-                            0x02,
-                            PropertyValue::FourByteInteger(message_expiry_interval),
-                        ),
-                    )
-                },
-            ),
-            0x03 => context("content type", read_string)(input)
-                .map(|(input, content_type)| (input, (0x03, PropertyValue::String(content_type)))),
-            0x08 => context("response topic", read_string)(input).map(|(input, response_topic)| {
-                (input, (0x08, PropertyValue::String(response_topic)))
-            }),
-            0x09 => {
-                context("correlation data", read_bytes)(input).map(|(input, correlation_data)| {
-                    (input, (0x09, PropertyValue::Binary(correlation_data)))
-                })
-            }
-            0x0B => context("subscription identifier", read_variable_bytes)(input).and_then(
-                |(input, subscription_identifier)| {
-                    if subscription_identifier.0 == 0 {
-                        return Err(NomErr::Error(VerboseError { errors: vec![] }));
-                    }
-                    Ok((
-                        input,
-                        (
-                            0x0B,
-                            PropertyValue::VariableByteInteger(subscription_identifier.0),
-                        ),
-                    ))
-                },
-            ),
-            0x11 => context("session expiry interval", be_u32)(input).map(
-                |(input, session_expiry_interval)| {
-                    (
-                        input,
-                        (
-                            0x11,
-                            PropertyValue::FourByteInteger(session_expiry_interval),
-                        ),
-                    )
-                },
-            ),
-            0x12 => context("assigned client identifier", read_string)(input).map(
-                |(input, assigned_client_identifier)| {
-                    (
-                        input,
-                        (0x12, PropertyValue::String(assigned_client_identifier)),
-                    )
-                },
-            ),
-            0x13 => {
-                context("server keep alive", be_u16)(input).map(|(input, server_keep_alive)| {
+    context("property value", be_u8)(input).and_then(|(input, property_id)| match property_id {
+        0x01 => context("payload format indicator", be_u8)(input).map(
+            |(input, payload_format_indicator)| {
+                (
+                    input,
                     (
-                        input,
-                        (0x13, PropertyValue::TwoByteInteger(server_keep_alive)),
-                    )
-                })
-            }
-            0x15 => context("authentication method", read_string)(input).map(
-                |(input, authentication_method)| {
-                    (input, (0x15, PropertyValue::String(authentication_method)))
-                },
-            ),
-            0x16 => context("authentication data", read_bytes)(input).map(
-                |(input, authentication_data)| {
-                    (input, (0x16, PropertyValue::Binary(authentication_data)))
-                },
-            ),
-            0x17 => context("request problem information", be_u8)(input).map(
-                |(input, request_problem_information)| {
+                        0x01,
+                        PropertyValue::Bit(payload_format_indicator & 0x01 == 1),
+                    ),
+                )
+            },
+        ),
+        0x02 => context("message expiry interval", be_u32)(input).map(
+            |(input, message_expiry_interval)| {
+                (
+                    input,
                     (
+                        0x02,
+                        PropertyValue::FourByteInteger(message_expiry_interval),
+                    ),
+                )
+            },
+        ),
+        0x03 => context("content type", read_string)(input)
+            .map(|(input, content_type)| (input, (0x03, PropertyValue::String(content_type)))),
+        0x08 => context("response topic", read_string)(input)
+            .map(|(input, response_topic)| (input, (0x08, PropertyValue::String(response_topic)))),
+        0x09 => context("correlation data", read_bytes)(input).map(|(input, correlation_data)| {
+            (input, (0x09, PropertyValue::Binary(correlation_data)))
+        }),
+        0x0B => context("subscription identifier", read_variable_bytes)(input).and_then(
+            |(input, subscription_identifier)| {
+                // a Subscription Identifier of 0 is a Protocol Error
+                if subscription_identifier.0 == 0 {
+                    return Err(NomErr::Error(DecodeError::from_external_error(
                         input,
-                        (
-                            0x17,
-                            PropertyValue::Bit(request_problem_information & 0x01 == 1),
-                        ),
-                    )
-                },
-            ),
-            0x18 => {
-                context("will delay interval", be_u32)(input).map(|(input, will_delay_interval)| {
+                        ErrorKind::Verify,
+                        Error::InvalidSubscriptionId,
+                    )));
+                }
+                Ok((
+                    input,
                     (
-                        input,
-                        (0x18, PropertyValue::FourByteInteger(will_delay_interval)),
-                    )
-                })
-            }
-            0x19 => context("request response information", be_u8)(input).map(
-                |(input, request_response_information)| {
+                        0x0B,
+                        PropertyValue::VariableByteInteger(subscription_identifier.0),
+                    ),
+                ))
+            },
+        ),
+        0x11 => context("session expiry interval", be_u32)(input).map(
+            |(input, session_expiry_interval)| {
+                (
+                    input,
                     (
-                        input,
-                        (
-                            0x19,
-                            PropertyValue::Bit(request_response_information & 0x01 == 1),
-                        ),
-                    )
-                },
-            ),
-            0x1A => context("response information", read_string)(input).map(
-                |(input, response_information)| {
-                    (input, (0x1A, PropertyValue::String(response_information)))
-                },
-            ),
-            0x1C => {
-                context("server reference", read_string)(input).map(|(input, server_reference)| {
-                    (input, (0x1C, PropertyValue::String(server_reference)))
-                })
-            }
-            0x1F => context("reason string", read_string)(input).map(|(input, reason_string)| {
-                (input, (0x1F, PropertyValue::String(reason_string)))
-            }),
-            0x21 => context("receive maximum", be_u16)(input).map(|(input, receive_maximum)| {
+                        0x11,
+                        PropertyValue::FourByteInteger(session_expiry_interval),
+                    ),
+                )
+            },
+        ),
+        0x12 => context("assigned client identifier", read_string)(input).map(
+            |(input, assigned_client_identifier)| {
                 (
                     input,
-                    (0x21, PropertyValue::TwoByteInteger(receive_maximum)),
+                    (0x12, PropertyValue::String(assigned_client_identifier)),
                 )
-            }),
-            0x22 => {
-                context("topic alias maximum", be_u16)(input).map(|(input, topic_alias_maximum)| {
-                    (
-                        input,
-                        (0x22, PropertyValue::TwoByteInteger(topic_alias_maximum)),
-                    )
-                })
-            }
-            0x23 => context("topic alias", be_u16)(input).map(|(input, topic_alias)| {
-                (input, (0x23, PropertyValue::TwoByteInteger(topic_alias)))
-            }),
-            0x24 => context("maximum qos", be_u8)(input)
-                .map(|(input, maximum_qos)| (input, (0x24, PropertyValue::Byte(maximum_qos)))),
-            0x25 => context("retain available", be_u8)(input).map(|(input, retain_available)| {
-                (input, (0x25, PropertyValue::Byte(retain_available)))
-            }),
-            0x26 => context("user property", pair(read_string, read_string))(input).map(
-                |(input, (name, value))| (input, (0x26, PropertyValue::StringPair(name, value))),
-            ),
-            0x27 => {
-                context("maximum packet size", be_u32)(input).map(|(input, maximum_packet_size)| {
-                    (
-                        input,
-                        (0x27, PropertyValue::FourByteInteger(maximum_packet_size)),
-                    )
-                })
-            }
-            0x28 => context("wildcard subscription available", be_u8)(input).map(
-                |(input, wildcard_subscription_available)| {
-                    (
-                        input,
-                        (0x28, PropertyValue::Byte(wildcard_subscription_available)),
-                    )
-                },
-            ),
-            0x29 => context("subscription identifier available", be_u8)(input).map(
-                |(input, subscription_identifier_available)| {
+            },
+        ),
+        0x13 => context("server keep alive", be_u16)(input).map(|(input, server_keep_alive)| {
+            (
+                input,
+                (0x13, PropertyValue::TwoByteInteger(server_keep_alive)),
+            )
+        }),
+        0x15 => context("authentication method", read_string)(input).map(
+            |(input, authentication_method)| {
+                (input, (0x15, PropertyValue::String(authentication_method)))
+            },
+        ),
+        0x16 => {
+            context("authentication data", read_bytes)(input).map(|(input, authentication_data)| {
+                (input, (0x16, PropertyValue::Binary(authentication_data)))
+            })
+        }
+        0x17 => context("request problem information", be_u8)(input).map(
+            |(input, request_problem_information)| {
+                (
+                    input,
                     (
-                        input,
-                        (0x29, PropertyValue::Byte(subscription_identifier_available)),
-                    )
-                },
-            ),
-            0x2A => context("shared subscription available", be_u8)(input).map(
-                |(input, shared_subscription_available)| {
+                        0x17,
+                        PropertyValue::Bit(request_problem_information & 0x01 == 1),
+                    ),
+                )
+            },
+        ),
+        0x18 => {
+            context("will delay interval", be_u32)(input).map(|(input, will_delay_interval)| {
+                (
+                    input,
+                    (0x18, PropertyValue::FourByteInteger(will_delay_interval)),
+                )
+            })
+        }
+        0x19 => context("request response information", be_u8)(input).map(
+            |(input, request_response_information)| {
+                (
+                    input,
                     (
-                        input,
-                        (0x2A, PropertyValue::Byte(shared_subscription_available)),
-                    )
-                },
+                        0x19,
+                        PropertyValue::Bit(request_response_information & 0x01 == 1),
+                    ),
+                )
+            },
+        ),
+        0x1A => context("response information", read_string)(input).map(
+            |(input, response_information)| {
+                (input, (0x1A, PropertyValue::String(response_information)))
+            },
+        ),
+        0x1C => context("server reference", read_string)(input).map(|(input, server_reference)| {
+            (input, (0x1C, PropertyValue::String(server_reference)))
+        }),
+        0x1F => context("reason string", read_string)(input)
+            .map(|(input, reason_string)| (input, (0x1F, PropertyValue::String(reason_string)))),
+        0x21 => context("receive maximum", be_u16)(input).map(|(input, receive_maximum)| {
+            (
+                input,
+                (0x21, PropertyValue::TwoByteInteger(receive_maximum)),
+            )
+        }),
+        0x22 => {
+            context("topic alias maximum", be_u16)(input).map(|(input, topic_alias_maximum)| {
+                (
+                    input,
+                    (0x22, PropertyValue::TwoByteInteger(topic_alias_maximum)),
+                )
+            })
+        }
+        0x23 => context("topic alias", be_u16)(input).map(|(input, topic_alias)| {
+            (input, (0x23, PropertyValue::TwoByteInteger(topic_alias)))
+        }),
+        0x24 => context("maximum qos", be_u8)(input)
+            .map(|(input, maximum_qos)| (input, (0x24, PropertyValue::Byte(maximum_qos)))),
+        0x25 => context("retain available", be_u8)(input).map(|(input, retain_available)| {
+            (input, (0x25, PropertyValue::Byte(retain_available)))
+        }),
+        0x26 => context("user property", pair(read_string, read_string))(input)
+            .map(|(input, (name, value))| (input, (0x26, PropertyValue::StringPair(name, value)))),
+        0x27 => {
+            context("maximum packet size", be_u32)(input).map(|(input, maximum_packet_size)| {
+                (
+                    input,
+                    (0x27, PropertyValue::FourByteInteger(maximum_packet_size)),
+                )
+            })
+        }
+        0x28 => context("wildcard subscription available", be_u8)(input).map(
+            |(input, wildcard_subscription_available)| {
+                (
+                    input,
+                    (0x28, PropertyValue::Byte(wildcard_subscription_available)),
+                )
+            },
+        ),
+        0x29 => context("subscription identifier available", be_u8)(input).map(
+            |(input, subscription_identifier_available)| {
+                (
+                    input,
+                    (0x29, PropertyValue::Byte(subscription_identifier_available)),
+                )
+            },
+        ),
+        0x2A => context("shared subscription available", be_u8)(input).map(
+            |(input, shared_subscription_available)| {
+                (
+                    input,
+                    (0x2A, PropertyValue::Byte(shared_subscription_available)),
+                )
+            },
+        ),
+        _ => Err(NomErr::Error(DecodeError::add_context(
+            input,
+            "unknown property identifier",
+            DecodeError::from_external_error(
+                input,
+                ErrorKind::Switch,
+                Error::InvalidPropertyType(format!(
+                    "unknown property identifier {:#04x}",
+                    property_id
+                )),
             ),
-            _ => Err(NomErr::Error(VerboseError { errors: vec![] })),
-        };
+        ))),
     })
 }
 
 #[cfg(test)]
 #[rustfmt::skip]
 mod tests_mqtt {
-    use crate::{mqtt5_property, parse};
+    use crate::error::Error;
+    use crate::packet::{Mqtt5Property, Packet, PacketType, PropertyValue, Qos};
+    use crate::reason_code::{DisconnectReasonCode, PubAckReasonCode};
+    use nom::error::VerboseErrorKind;
+    use nom::{Err as NomErr, Needed};
+    use std::convert::TryFrom;
+    use crate::{connect_preamble, mqtt5_property, parse, subscription_options};
 
     #[test]
     fn test_mqtt5_property() {
@@ -950,20 +1189,301 @@ mod tests_mqtt {
         }
     }
 
+    #[test]
+    fn test_user_property_order() {
+        let vec = &[
+            26,   // properties length
+            0x26, 0x00, 0x01, b'b', 0x00, 0x01, b'1', // user property
+            0x26, 0x00, 0x01, b'a', 0x00, 0x01, b'2', // user property
+            0x1F, 0x00, 0x02, b'o', b'k', // reason string
+            0x26, 0x00, 0x01, b'b', 0x00, 0x01, b'3', // duplicate user property
+        ];
+        let (_, property) = mqtt5_property(vec).unwrap();
+        assert_eq!(property.user_properties, vec![("b", "1"), ("a", "2"), ("b", "3")]);
+        assert_eq!(property.properties.get(&0x1F), Some(&PropertyValue::String("ok")));
+        assert_eq!(property.properties.get(&0x26), None);
+    }
+
+    #[test]
+    fn test_mqtt5_property_empty() {
+        let (input, property) = mqtt5_property(&[0, 0xFF]).unwrap();
+        assert_eq!(input, &[0xFF]);
+        assert_eq!(property, Mqtt5Property::new());
+    }
+
+    #[test]
+    fn test_mqtt5_property_unknown_id() {
+        let vec = &[
+            7,    // properties length
+            0x21, 0x00, 0x0A, // receive maximum
+            0x07, 0x00, 0x00, 0x00, // unknown property id
+        ];
+        match mqtt5_property(vec) {
+            Err(NomErr::Error(e)) => {
+                assert!(e.errors.contains(&(&vec[5..], VerboseErrorKind::Context("unknown property identifier"))));
+                assert!(e.errors.contains(&(&vec[..], VerboseErrorKind::Context("mqtt5 property"))));
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_mqtt5_property_truncated() {
+        // property length exceeds the input
+        assert!(mqtt5_property(&[5, 0x21, 0x00]).is_err());
+        // string length exceeds the property length
+        assert!(mqtt5_property(&[4, 0x1F, 0x00, 0x05, b'a', b'b', b'c']).is_err());
+        // four byte integer cut short by the property length
+        assert!(mqtt5_property(&[3, 0x02, 0x00, 0x00, 0x00, 0x00]).is_err());
+        // property length over more than 4 bytes
+        assert!(mqtt5_property(&[0x80, 0x80, 0x80, 0x80, 0x00]).is_err());
+        assert!(mqtt5_property(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_subscription_options() {
+        let (_, options) = subscription_options(&[0b0010_1110]).unwrap();
+        assert_eq!(options.maximum_qos, Qos::ExactlyOnce);
+        assert!(options.no_local);
+        assert!(options.retain_as_published);
+        assert_eq!(options.retain_handling, 2);
+
+        let (_, options) = subscription_options(&[0b0001_0001]).unwrap();
+        assert_eq!(options.maximum_qos, Qos::AtLeastOnce);
+        assert!(!options.no_local);
+        assert!(!options.retain_as_published);
+        assert_eq!(options.retain_handling, 1);
+
+        // reserved bits
+        assert!(subscription_options(&[0b0100_0000]).is_err());
+        assert!(subscription_options(&[0b1000_0000]).is_err());
+        // retain handling 3
+        assert!(subscription_options(&[0b0011_0000]).is_err());
+        // qos 3
+        assert!(subscription_options(&[0b0000_0011]).is_err());
+        // invalid options on a later topic filter fail the whole subscribe
+        let vec = &[
+            0b1000_0010, 11,
+            0x00, 0x0A, 0, // packet identifier, properties
+            0x00, 0x01, b'a', 0b0000_0001,
+            0x00, 0x01, b'b', 0b0100_0000,
+        ];
+        assert!(parse(vec).is_err());
+        // a truncated topic filter in an unsubscribe
+        assert!(parse(&[0b1010_0010, 7, 0x00, 0x0A, 0, 0x00, 0x01, b'a', 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_bytes_no_panic() {
+        let parse_error = |bytes: &[u8]| {
+            let err = Error::from(parse(bytes).unwrap_err());
+            assert_eq!(err.disconnect_reason_code(), Some(DisconnectReasonCode::MalformedPacket));
+            err
+        };
+        // reserved packet type 0
+        assert_eq!(parse_error(&[0b0000_0000, 0]), Error::InvalidPacketType(0));
+        // qos 3 in fixed header
+        assert_eq!(parse_error(&[0b0011_0110, 0]), Error::InvalidQos(3));
+        // unknown puback reason code
+        assert_eq!(parse_error(&[0b0100_0000, 4, 0x00, 0x01, 0x05, 0]), Error::InvalidReasonCode(0x05));
+        // unknown suback reason code after a valid one
+        assert_eq!(parse_error(&[0b1001_0000, 5, 0x00, 0x01, 0, 0x00, 0x03]), Error::InvalidReasonCode(0x03));
+        // will qos 3 in connect flags
+        assert_eq!(parse_error(&[
+            0b0001_0000, 13,
+            0x00, 0x04, b'M', b'Q', b'T', b'T', 0x05,
+            0b0001_1110, 0x00, 0x10, 0,
+            0x00, 0x00,
+        ]), Error::InvalidQos(3));
+        // variable byte integer with more than 4 bytes in a property length
+        assert_eq!(
+            parse_error(&[0b1011_0000, 13, 0x00, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]),
+            Error::MalformedVariableByteInteger
+        );
+        assert_eq!(
+            parse_error(&[0b0100_0000, 8, 0x00, 0x01, 0x00, 0x80, 0x80, 0x80, 0x80, 0x00]),
+            Error::MalformedVariableByteInteger
+        );
+    }
+
+    #[test]
+    fn test_packet_id_zero() {
+        // packets with a zero packet identifier, and the offset of its low byte
+        for (vec, offset) in &[
+            (&[0b0011_0010, 6, 0x00, 0x01, b't', 0x00, 0x00, 0][..], 6), // publish qos 1
+            (&[0b0100_0000, 4, 0x00, 0x00, 0x00, 0], 3), // puback
+            (&[0b0101_0000, 4, 0x00, 0x00, 0x00, 0], 3), // pubrec
+            (&[0b0110_0010, 4, 0x00, 0x00, 0x00, 0], 3), // pubrel
+            (&[0b0111_0000, 4, 0x00, 0x00, 0x00, 0], 3), // pubcomp
+            (&[0b1000_0010, 7, 0x00, 0x00, 0, 0x00, 0x01, b't', 0x00], 3), // subscribe
+            (&[0b1001_0000, 4, 0x00, 0x00, 0, 0x00], 3), // suback
+            (&[0b1010_0010, 6, 0x00, 0x00, 0, 0x00, 0x01, b't'], 3), // unsubscribe
+            (&[0b1011_0000, 4, 0x00, 0x00, 0, 0x00], 3), // unsuback
+        ] {
+            assert_eq!(Error::from(parse(vec).unwrap_err()), Error::InvalidPacketId, "{:?}", vec);
+            let mut vec = vec.to_vec();
+            vec[*offset] = 1;
+            assert!(parse(&vec).is_ok(), "{:?}", vec);
+        }
+        // qos 0 publish carries no packet identifier
+        assert!(parse(&[0b0011_0000, 4, 0x00, 0x01, b't', 0]).is_ok());
+    }
+
+    #[test]
+    fn test_optional_reason_code() {
+        // reason code and properties left out
+        match parse(&[0b1110_0000, 0]) {
+            Ok((_, Packet::Disconnect(disconnect))) => {
+                assert_eq!(disconnect.variable_header.disconnect_reason_code, DisconnectReasonCode::NormalDisconnection);
+                assert_eq!(disconnect.variable_header.disconnect_property, Mqtt5Property::new());
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        match parse(&[0b0100_0000, 2, 0x00, 0x0A]) {
+            Ok((_, Packet::PubAck(puback))) => {
+                assert_eq!(puback.variable_header.packet_id, 0x0A);
+                assert_eq!(puback.variable_header.puback_reason_code, PubAckReasonCode::Success);
+                assert_eq!(puback.variable_header.puback_property, Mqtt5Property::new());
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert!(matches!(parse(&[0b0101_0000, 2, 0x00, 0x0A]), Ok((_, Packet::PubRec(_)))));
+        assert!(matches!(parse(&[0b0110_0010, 2, 0x00, 0x0A]), Ok((_, Packet::PubRel(_)))));
+        assert!(matches!(parse(&[0b0111_0000, 2, 0x00, 0x0A]), Ok((_, Packet::PubComp(_)))));
+        assert!(matches!(parse(&[0b1111_0000, 0]), Ok((_, Packet::Auth(_)))));
+        // properties left out after the reason code
+        match parse(&[0b0100_0000, 3, 0x00, 0x0A, 0x10]) {
+            Ok((_, Packet::PubAck(puback))) => {
+                assert_eq!(puback.variable_header.puback_reason_code, PubAckReasonCode::NoMatchingSubscribers);
+                assert_eq!(puback.variable_header.puback_property, Mqtt5Property::new());
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        match parse(&[0b1110_0000, 1, 0x04]) {
+            Ok((_, Packet::Disconnect(disconnect))) => {
+                assert_eq!(disconnect.variable_header.disconnect_reason_code, DisconnectReasonCode::DisconnectWithWillMessage);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        // the packet identifier is not optional
+        assert_eq!(Error::from(parse(&[0b0100_0000, 1, 0x00]).unwrap_err()), Error::MalformedPacket);
+        assert_eq!(Error::from(parse(&[0b0100_0000, 0]).unwrap_err()), Error::MalformedPacket);
+    }
+
+    #[test]
+    fn test_connack_session_present() {
+        for (flag, session_present) in &[(0x00, false), (0x01, true)] {
+            match parse(&[0b0010_0000, 3, *flag, 0x00, 0]) {
+                Ok((_, Packet::ConnAck(connack))) => {
+                    assert_eq!(connack.variable_header.connack_flags.session_present, *session_present)
+                }
+                res => panic!("unexpected result: {:?}", res),
+            }
+        }
+        // reserved bits set
+        assert!(parse(&[0b0010_0000, 3, 0x02, 0x00, 0]).is_err());
+        assert!(parse(&[0b0010_0000, 3, 0x81, 0x00, 0]).is_err());
+    }
+
+    #[test]
+    fn test_reason_code_try_from() {
+        assert_eq!(PubAckReasonCode::try_from(0x10), Ok(PubAckReasonCode::NoMatchingSubscribers));
+        assert_eq!(PubAckReasonCode::try_from(0x05), Err(Error::InvalidReasonCode(0x05)));
+        assert_eq!(PacketType::try_from(0), Err(Error::InvalidPacketType(0)));
+        assert_eq!(Qos::try_from(3), Err(Error::InvalidQos(3)));
+    }
+
+    #[test]
+    fn test_parse_incomplete() {
+        let vec = &[
+            0b0100_0000, 4, 0x00, 0x0A, 0x00, 0, // puback
+            0b1100_0000, 0, // pingreq
+        ];
+        assert_eq!(parse(&[]), Err(NomErr::Incomplete(Needed::new(2))));
+        assert_eq!(parse(&vec[..1]), Err(NomErr::Incomplete(Needed::new(1))));
+        assert_eq!(parse(&vec[..3]), Err(NomErr::Incomplete(Needed::new(3))));
+        assert_eq!(parse(&vec[..5]), Err(NomErr::Incomplete(Needed::new(1))));
+        // remaining length spread over several bytes
+        assert_eq!(parse(&[0b0011_0000, 0x80]), Err(NomErr::Incomplete(Needed::new(1))));
+        assert_eq!(parse(&[0b0011_0000, 0x80, 0x01]), Err(NomErr::Incomplete(Needed::new(128))));
+        // remaining length longer than 4 bytes
+        assert!(matches!(parse(&[0b0011_0000, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]), Err(NomErr::Error(_))));
+        // a bad first byte or Remaining Length fails before the rest of the packet is asked for
+        let parse_error = |bytes: &[u8]| Error::from(parse(bytes).unwrap_err());
+        assert_eq!(parse_error(&[0b0000_0000, 0xFF, 0xFF, 0xFF, 0x7F]), Error::InvalidPacketType(0));
+        assert_eq!(parse_error(&[0b0000_0000]), Error::InvalidPacketType(0));
+        assert_eq!(parse_error(&[0b0011_0110, 0xFF, 0xFF, 0xFF, 0x7F]), Error::InvalidQos(3));
+        assert_eq!(parse_error(&[0b1100_0000, 0xFF, 0xFF, 0xFF, 0x7F]), Error::MalformedFixedHeader);
+        // reserved flags
+        assert_eq!(parse_error(&[0b0100_0001, 0xFF, 0xFF, 0xFF, 0x7F]), Error::MalformedFixedHeader);
+        assert_eq!(parse_error(&[0b1000_0000, 0xFF, 0xFF, 0xFF, 0x7F]), Error::MalformedFixedHeader);
+
+        let (input, packet) = parse(vec).unwrap();
+        assert!(matches!(packet, Packet::PubAck(_)));
+        assert_eq!(input, &vec[6..]);
+        let (input, packet) = parse(input).unwrap();
+        assert!(matches!(packet, Packet::PingReq(_)));
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn test_connect_preamble() {
+        let vec = &[
+            0b0001_0000u8, 0xFF, 0xFF, 0xFF, 0x7F, // fixed header announcing the maximum remaining length
+            0x00, 0x04, b'M', b'Q', b'T', b'T', // protocol name
+            0x05,          // protocol version
+            0b1100_1110u8, // connect flag
+        ];
+        for end in 0..vec.len() {
+            assert_eq!(connect_preamble(&vec[..end]), Ok(None));
+        }
+        let (fixed_header, connect_flags) = connect_preamble(vec).unwrap().unwrap();
+        assert_eq!(fixed_header.remaining_length, 268_435_455);
+        assert!(connect_flags.username_flag && connect_flags.will_flag);
+        assert_eq!(connect_flags.will_qos, Qos::AtLeastOnce);
+
+        let vec = &[0b0001_0000u8, 0xFF, 0x7F, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0x02];
+        assert_eq!(connect_preamble(vec), Err(Error::InvalidProtocol("MQTT".to_string(), 4)));
+        let vec = &[0b0001_0000u8, 0xFF, 0x7F, 0x00, 0x06, b'M', b'Q', b'I', b's', b'd', b'p', 0x03, 0x02];
+        assert_eq!(connect_preamble(vec), Err(Error::InvalidProtocol("MQIsdp".to_string(), 3)));
+        // a long protocol name is refused before it arrives
+        let vec = &[0b0001_0000u8, 0xFF, 0xFF, 0xFF, 0x7F, 0xFF, 0xFF, b'M', b'Q'];
+        for end in 7..vec.len() {
+            assert_eq!(connect_preamble(&vec[..end]), Err(Error::InvalidProtocol(String::new(), 0)));
+        }
+        // protocol name longer than the remaining length
+        assert_eq!(connect_preamble(&[0b0001_0000u8, 4, 0x00, 0x04]), Err(Error::MalformedPacket));
+        // will qos 3
+        let vec = &[0b0001_0000u8, 10, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x05, 0b0001_1100];
+        assert_eq!(connect_preamble(vec), Err(Error::MalformedPacket));
+        // reserved bit 0
+        let vec = &[0b0001_0000u8, 10, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x05, 0b0000_0011];
+        assert_eq!(connect_preamble(vec), Err(Error::MalformedPacket));
+        // will qos or will retain without the will flag
+        let vec = &[0b0001_0000u8, 10, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x05, 0b0000_1010];
+        assert_eq!(connect_preamble(vec), Err(Error::MalformedPacket));
+        let vec = &[0b0001_0000u8, 10, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x05, 0b0010_0010];
+        assert_eq!(connect_preamble(vec), Err(Error::MalformedPacket));
+        // will qos and will retain with the will flag
+        let vec = &[0b0001_0000u8, 10, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x05, 0b0011_0110];
+        assert!(connect_preamble(vec).unwrap().is_some());
+        assert_eq!(connect_preamble(&[0b0011_0000u8]), Err(Error::InvalidPacketType(3)));
+    }
+
     #[test]
     fn test_connect() {
         let vec = &[
             0b0001_0000u8, 52, // fixed header
-            0x00, 0x04, 'M' as u8, 'Q' as u8, 'T' as u8, 'T' as u8,     // protocol name
+            0x00, 0x04, b'M', b'Q', b'T', b'T',     // protocol name
             0x05,          // protocol version
             0b1100_1110u8, // connect flag
             0x00, 0x10, // keep alive
             0x05, 0x11, 0x00, 0x00, 0x00, 0x10, // connect properties
-            0x00, 0x03, 'c' as u8, 'i' as u8, 'd' as u8, // client id
+            0x00, 0x03, b'c', b'i', b'd', // client id
             0x05, 0x02, 0x00, 0x00, 0x00, 0x10, // will properties
-            0x00, 0x04, 'w' as u8, 'i' as u8, 'l' as u8, 'l' as u8, // will topic
-            0x00, 0x01, 'p' as u8, // will payload
-            0x00, 0x06, 'i' as u8, 'a' as u8, 'm' as u8, 'a' as u8, 'z' as u8, 'y' as u8, // username
+            0x00, 0x04, b'w', b'i', b'l', b'l', // will topic
+            0x00, 0x01, b'p', // will payload
+            0x00, 0x06, b'i', b'a', b'm', b'a', b'z', b'y', // username
             0x00, 0x06, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
         ];
         match parse(vec) {
@@ -983,7 +1503,7 @@ mod tests_mqtt {
             0x00, 0x10, // packet identifier
             5,    // properties length
             0x1F, // property id
-            0x00, 0x02, 'I' as u8, 'a' as u8, // reason string
+            0x00, 0x02, b'I', b'a', // reason string
             0x00, 0x01, 0x02,
         ];
         match parse(vec) {
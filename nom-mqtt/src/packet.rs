@@ -1,9 +1,11 @@
+use crate::error::Error;
 use crate::reason_code::{
     AuthenticateReasonCode, ConnectReasonCode, DisconnectReasonCode, PubAckReasonCode,
     PubCompReasonCode, PubRecReasonCode, PubRelReasonCode, SubscribeReasonCode,
     UnSubscribeReasonCode,
 };
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Protocol {
@@ -322,25 +324,27 @@ pub enum PacketType {
     AUTH = 15,
 }
 
-impl From<u8> for PacketType {
-    fn from(byte: u8) -> Self {
+impl TryFrom<u8> for PacketType {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
         match byte {
-            1 => PacketType::CONNECT,
-            2 => PacketType::CONNACK,
-            3 => PacketType::PUBLISH,
-            4 => PacketType::PUBACK,
-            5 => PacketType::PUBREC,
-            6 => PacketType::PUBREL,
-            7 => PacketType::PUBCOMP,
-            8 => PacketType::SUBSCRIBE,
-            9 => PacketType::SUBACK,
-            10 => PacketType::UNSUBSCRIBE,
-            11 => PacketType::UNSUBACK,
-            12 => PacketType::PINGREQ,
-            13 => PacketType::PINGRESP,
-            14 => PacketType::DISCONNECT,
-            15 => PacketType::AUTH,
-            _ => unimplemented!("no other packet type supported"),
+            1 => Ok(PacketType::CONNECT),
+            2 => Ok(PacketType::CONNACK),
+            3 => Ok(PacketType::PUBLISH),
+            4 => Ok(PacketType::PUBACK),
+            5 => Ok(PacketType::PUBREC),
+            6 => Ok(PacketType::PUBREL),
+            7 => Ok(PacketType::PUBCOMP),
+            8 => Ok(PacketType::SUBSCRIBE),
+            9 => Ok(PacketType::SUBACK),
+            10 => Ok(PacketType::UNSUBSCRIBE),
+            11 => Ok(PacketType::UNSUBACK),
+            12 => Ok(PacketType::PINGREQ),
+            13 => Ok(PacketType::PINGRESP),
+            14 => Ok(PacketType::DISCONNECT),
+            15 => Ok(PacketType::AUTH),
+            _ => Err(Error::InvalidPacketType(byte)),
         }
     }
 }
@@ -357,13 +361,15 @@ pub enum Qos {
     Reserved = 3,
 }
 
-impl From<u8> for Qos {
-    fn from(byte: u8) -> Self {
+impl TryFrom<u8> for Qos {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
         match byte {
-            0 => Qos::AtMostOnce,
-            1 => Qos::AtLeastOnce,
-            2 => Qos::ExactlyOnce,
-            _ => unimplemented!("no other qos supported"),
+            0 => Ok(Qos::AtMostOnce),
+            1 => Ok(Qos::AtLeastOnce),
+            2 => Ok(Qos::ExactlyOnce),
+            _ => Err(Error::InvalidQos(byte)),
         }
     }
 }
@@ -372,6 +378,8 @@ impl From<u8> for Qos {
 pub struct Mqtt5Property<'a> {
     pub property_length: usize,
     pub properties: HashMap<u32, PropertyValue<'a>>,
+    /// User Properties are kept in the order they appear on the wire, the same name is allowed to appear more than once
+    pub user_properties: Vec<(&'a str, &'a str)>,
 }
 
 impl<'a> Mqtt5Property<'a> {
@@ -379,10 +387,17 @@ impl<'a> Mqtt5Property<'a> {
         Mqtt5Property {
             property_length: 0,
             properties: HashMap::<u32, PropertyValue>::default(),
+            user_properties: Vec::new(),
         }
     }
 }
 
+impl<'a> Default for Mqtt5Property<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PropertyType {
     PayloadFormatIndicator = 0x01,
@@ -414,6 +429,46 @@ pub enum PropertyType {
     SharedSubscriptionAvailable = 0x2A,
 }
 
+impl TryFrom<u8> for PropertyType {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        match byte {
+            0x01 => Ok(PropertyType::PayloadFormatIndicator),
+            0x02 => Ok(PropertyType::MessageExpiryInterval),
+            0x03 => Ok(PropertyType::ContentType),
+            0x08 => Ok(PropertyType::ResponseTopic),
+            0x09 => Ok(PropertyType::CorrelationData),
+            0x0B => Ok(PropertyType::SubscriptionIdentifier),
+            0x11 => Ok(PropertyType::SessionExpiryInterval),
+            0x12 => Ok(PropertyType::AssignedClientIdentifier),
+            0x13 => Ok(PropertyType::ServerKeepAlive),
+            0x15 => Ok(PropertyType::AuthenticationMethod),
+            0x16 => Ok(PropertyType::AuthenticationData),
+            0x17 => Ok(PropertyType::RequestProblemInformation),
+            0x18 => Ok(PropertyType::WillDelayInterval),
+            0x19 => Ok(PropertyType::RequestResponseInformation),
+            0x1A => Ok(PropertyType::ResponseInformation),
+            0x1C => Ok(PropertyType::ServerReference),
+            0x1F => Ok(PropertyType::ReasonString),
+            0x21 => Ok(PropertyType::ReceiveMaximum),
+            0x22 => Ok(PropertyType::TopicAliasMaximum),
+            0x23 => Ok(PropertyType::TopicAlias),
+            0x24 => Ok(PropertyType::MaximumQos),
+            0x25 => Ok(PropertyType::RetainAvailable),
+            0x26 => Ok(PropertyType::UserProperty),
+            0x27 => Ok(PropertyType::MaximumPacketSize),
+            0x28 => Ok(PropertyType::WildcardSubscriptionAvailable),
+            0x29 => Ok(PropertyType::SubscriptionIdentifierAvailable),
+            0x2A => Ok(PropertyType::SharedSubscriptionAvailable),
+            _ => Err(Error::InvalidPropertyType(format!(
+                "unknown property identifier {:#04x}",
+                byte
+            ))),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum PropertyValue<'a> {
     Bit(bool),
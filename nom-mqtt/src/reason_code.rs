@@ -1,3 +1,6 @@
+use crate::error::Error;
+use std::convert::TryFrom;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PubAckReasonCode {
     /// 0[0x00], The message is accepted. Publication of the QoS 1 message proceeds
@@ -23,19 +26,21 @@ pub enum PubAckReasonCode {
     PayloadFormatInvalid = 0x99,
 }
 
-impl From<u8> for PubAckReasonCode {
-    fn from(byte: u8) -> Self {
+impl TryFrom<u8> for PubAckReasonCode {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
         match byte {
-            0 => PubAckReasonCode::Success,
-            16 => PubAckReasonCode::NoMatchingSubscribers,
-            128 => PubAckReasonCode::UnspecifiedError,
-            131 => PubAckReasonCode::ImplementationSpecificError,
-            135 => PubAckReasonCode::NotAuthorized,
-            144 => PubAckReasonCode::TopicNameInvalid,
-            145 => PubAckReasonCode::PacketIdentifierInUse,
-            151 => PubAckReasonCode::QuotaExceeded,
-            153 => PubAckReasonCode::PayloadFormatInvalid,
-            _ => unimplemented!("no other puback reason code support"),
+            0 => Ok(PubAckReasonCode::Success),
+            16 => Ok(PubAckReasonCode::NoMatchingSubscribers),
+            128 => Ok(PubAckReasonCode::UnspecifiedError),
+            131 => Ok(PubAckReasonCode::ImplementationSpecificError),
+            135 => Ok(PubAckReasonCode::NotAuthorized),
+            144 => Ok(PubAckReasonCode::TopicNameInvalid),
+            145 => Ok(PubAckReasonCode::PacketIdentifierInUse),
+            151 => Ok(PubAckReasonCode::QuotaExceeded),
+            153 => Ok(PubAckReasonCode::PayloadFormatInvalid),
+            _ => Err(Error::InvalidReasonCode(byte)),
         }
     }
 }
@@ -65,19 +70,21 @@ pub enum PubRecReasonCode {
     PayloadFormatInvalid = 0x99,
 }
 
-impl From<u8> for PubRecReasonCode {
-    fn from(byte: u8) -> Self {
+impl TryFrom<u8> for PubRecReasonCode {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
         match byte {
-            0 => PubRecReasonCode::Success,
-            16 => PubRecReasonCode::NoMatchingSubscribers,
-            128 => PubRecReasonCode::UnspecifiedError,
-            131 => PubRecReasonCode::ImplementationSpecificError,
-            135 => PubRecReasonCode::NotAuthorized,
-            144 => PubRecReasonCode::TopicNameInvalid,
-            145 => PubRecReasonCode::PacketIdentifierInUse,
-            151 => PubRecReasonCode::QuotaExceeded,
-            153 => PubRecReasonCode::PayloadFormatInvalid,
-            _ => unimplemented!("no other pubrec reason code support"),
+            0 => Ok(PubRecReasonCode::Success),
+            16 => Ok(PubRecReasonCode::NoMatchingSubscribers),
+            128 => Ok(PubRecReasonCode::UnspecifiedError),
+            131 => Ok(PubRecReasonCode::ImplementationSpecificError),
+            135 => Ok(PubRecReasonCode::NotAuthorized),
+            144 => Ok(PubRecReasonCode::TopicNameInvalid),
+            145 => Ok(PubRecReasonCode::PacketIdentifierInUse),
+            151 => Ok(PubRecReasonCode::QuotaExceeded),
+            153 => Ok(PubRecReasonCode::PayloadFormatInvalid),
+            _ => Err(Error::InvalidReasonCode(byte)),
         }
     }
 }
@@ -91,12 +98,14 @@ pub enum PubRelReasonCode {
     PacketIdentifierNotFound = 0x92,
 }
 
-impl From<u8> for PubRelReasonCode {
-    fn from(byte: u8) -> Self {
+impl TryFrom<u8> for PubRelReasonCode {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
         match byte {
-            0 => PubRelReasonCode::Success,
-            146 => PubRelReasonCode::PacketIdentifierNotFound,
-            _ => unimplemented!("no other pubrel reason code support"),
+            0 => Ok(PubRelReasonCode::Success),
+            146 => Ok(PubRelReasonCode::PacketIdentifierNotFound),
+            _ => Err(Error::InvalidReasonCode(byte)),
         }
     }
 }
@@ -110,12 +119,14 @@ pub enum PubCompReasonCode {
     PacketIdentifierNotFound = 0x92,
 }
 
-impl From<u8> for PubCompReasonCode {
-    fn from(byte: u8) -> Self {
+impl TryFrom<u8> for PubCompReasonCode {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
         match byte {
-            0 => PubCompReasonCode::Success,
-            146 => PubCompReasonCode::PacketIdentifierNotFound,
-            _ => unimplemented!("no other pubcomp reason code support"),
+            0 => Ok(PubCompReasonCode::Success),
+            146 => Ok(PubCompReasonCode::PacketIdentifierNotFound),
+            _ => Err(Error::InvalidReasonCode(byte)),
         }
     }
 }
@@ -151,22 +162,24 @@ pub enum SubscribeReasonCode {
     WildcardSubscriptionNotSupported = 0xA2,
 }
 
-impl From<u8> for SubscribeReasonCode {
-    fn from(byte: u8) -> Self {
+impl TryFrom<u8> for SubscribeReasonCode {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
         match byte {
-            0 => SubscribeReasonCode::GrantedQos0,
-            1 => SubscribeReasonCode::GrantedQos1,
-            2 => SubscribeReasonCode::GrantedQos2,
-            128 => SubscribeReasonCode::UnspecifiedError,
-            131 => SubscribeReasonCode::ImplementationSpecificError,
-            135 => SubscribeReasonCode::NotAuthorized,
-            143 => SubscribeReasonCode::TopicFilterInvalid,
-            145 => SubscribeReasonCode::PacketIdentifierInUse,
-            151 => SubscribeReasonCode::QuotaExceeded,
-            158 => SubscribeReasonCode::SharedSubscriptionNotSupported,
-            161 => SubscribeReasonCode::SubscriptionIdentifierNotSupported,
-            162 => SubscribeReasonCode::WildcardSubscriptionNotSupported,
-            _ => unimplemented!("no other subscribe reason code support"),
+            0 => Ok(SubscribeReasonCode::GrantedQos0),
+            1 => Ok(SubscribeReasonCode::GrantedQos1),
+            2 => Ok(SubscribeReasonCode::GrantedQos2),
+            128 => Ok(SubscribeReasonCode::UnspecifiedError),
+            131 => Ok(SubscribeReasonCode::ImplementationSpecificError),
+            135 => Ok(SubscribeReasonCode::NotAuthorized),
+            143 => Ok(SubscribeReasonCode::TopicFilterInvalid),
+            145 => Ok(SubscribeReasonCode::PacketIdentifierInUse),
+            151 => Ok(SubscribeReasonCode::QuotaExceeded),
+            158 => Ok(SubscribeReasonCode::SharedSubscriptionNotSupported),
+            161 => Ok(SubscribeReasonCode::SubscriptionIdentifierNotSupported),
+            162 => Ok(SubscribeReasonCode::WildcardSubscriptionNotSupported),
+            _ => Err(Error::InvalidReasonCode(byte)),
         }
     }
 }
@@ -190,17 +203,19 @@ pub enum UnSubscribeReasonCode {
     PacketIdentifierInUse = 0x91,
 }
 
-impl From<u8> for UnSubscribeReasonCode {
-    fn from(byte: u8) -> Self {
+impl TryFrom<u8> for UnSubscribeReasonCode {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
         match byte {
-            0 => UnSubscribeReasonCode::Success,
-            17 => UnSubscribeReasonCode::NoSubscriptionFound,
-            128 => UnSubscribeReasonCode::UnspecifiedError,
-            131 => UnSubscribeReasonCode::ImplementationSpecificError,
-            135 => UnSubscribeReasonCode::NotAuthorized,
-            143 => UnSubscribeReasonCode::TopicFilterInValid,
-            145 => UnSubscribeReasonCode::PacketIdentifierInUse,
-            _ => unimplemented!("no other unsubscribe reason code support"),
+            0 => Ok(UnSubscribeReasonCode::Success),
+            17 => Ok(UnSubscribeReasonCode::NoSubscriptionFound),
+            128 => Ok(UnSubscribeReasonCode::UnspecifiedError),
+            131 => Ok(UnSubscribeReasonCode::ImplementationSpecificError),
+            135 => Ok(UnSubscribeReasonCode::NotAuthorized),
+            143 => Ok(UnSubscribeReasonCode::TopicFilterInValid),
+            145 => Ok(UnSubscribeReasonCode::PacketIdentifierInUse),
+            _ => Err(Error::InvalidReasonCode(byte)),
         }
     }
 }
@@ -267,39 +282,41 @@ pub enum DisconnectReasonCode {
     WildcardSubscriptionsNotSupported = 0xA2,
 }
 
-impl From<u8> for DisconnectReasonCode {
-    fn from(byte: u8) -> Self {
+impl TryFrom<u8> for DisconnectReasonCode {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
         match byte {
-            0 => DisconnectReasonCode::NormalDisconnection,
-            4 => DisconnectReasonCode::DisconnectWithWillMessage,
-            128 => DisconnectReasonCode::UnspecifiedError,
-            129 => DisconnectReasonCode::MalformedPacket,
-            130 => DisconnectReasonCode::ProtocolError,
-            131 => DisconnectReasonCode::ImplementationSpecificError,
-            135 => DisconnectReasonCode::NotAuthorized,
-            137 => DisconnectReasonCode::ServerBusy,
-            139 => DisconnectReasonCode::ServerShuttingDown,
-            141 => DisconnectReasonCode::KeepAliveTimeout,
-            142 => DisconnectReasonCode::SessionTakenOver,
-            143 => DisconnectReasonCode::TopicFilterInvalid,
-            144 => DisconnectReasonCode::TopicNameInvalid,
-            147 => DisconnectReasonCode::ReceiveMaximumExceeded,
-            148 => DisconnectReasonCode::TopicAliasInvalid,
-            149 => DisconnectReasonCode::PacketTooLarge,
-            150 => DisconnectReasonCode::MessageRateTooHigh,
-            151 => DisconnectReasonCode::QuotaExceeded,
-            152 => DisconnectReasonCode::AdministrativeAction,
-            153 => DisconnectReasonCode::PayloadFormatInvalid,
-            154 => DisconnectReasonCode::RetainNotSupported,
-            155 => DisconnectReasonCode::QosNotSupported,
-            156 => DisconnectReasonCode::UseAnotherServer,
-            157 => DisconnectReasonCode::ServerMoved,
-            158 => DisconnectReasonCode::SharedSubscriptionNotSupported,
-            159 => DisconnectReasonCode::ConnectionRateExceeded,
-            160 => DisconnectReasonCode::MaximumConnectTime,
-            161 => DisconnectReasonCode::SubscriptionIdentifiersNotSupported,
-            162 => DisconnectReasonCode::WildcardSubscriptionsNotSupported,
-            _ => unimplemented!("no other disconnect reason code support"),
+            0 => Ok(DisconnectReasonCode::NormalDisconnection),
+            4 => Ok(DisconnectReasonCode::DisconnectWithWillMessage),
+            128 => Ok(DisconnectReasonCode::UnspecifiedError),
+            129 => Ok(DisconnectReasonCode::MalformedPacket),
+            130 => Ok(DisconnectReasonCode::ProtocolError),
+            131 => Ok(DisconnectReasonCode::ImplementationSpecificError),
+            135 => Ok(DisconnectReasonCode::NotAuthorized),
+            137 => Ok(DisconnectReasonCode::ServerBusy),
+            139 => Ok(DisconnectReasonCode::ServerShuttingDown),
+            141 => Ok(DisconnectReasonCode::KeepAliveTimeout),
+            142 => Ok(DisconnectReasonCode::SessionTakenOver),
+            143 => Ok(DisconnectReasonCode::TopicFilterInvalid),
+            144 => Ok(DisconnectReasonCode::TopicNameInvalid),
+            147 => Ok(DisconnectReasonCode::ReceiveMaximumExceeded),
+            148 => Ok(DisconnectReasonCode::TopicAliasInvalid),
+            149 => Ok(DisconnectReasonCode::PacketTooLarge),
+            150 => Ok(DisconnectReasonCode::MessageRateTooHigh),
+            151 => Ok(DisconnectReasonCode::QuotaExceeded),
+            152 => Ok(DisconnectReasonCode::AdministrativeAction),
+            153 => Ok(DisconnectReasonCode::PayloadFormatInvalid),
+            154 => Ok(DisconnectReasonCode::RetainNotSupported),
+            155 => Ok(DisconnectReasonCode::QosNotSupported),
+            156 => Ok(DisconnectReasonCode::UseAnotherServer),
+            157 => Ok(DisconnectReasonCode::ServerMoved),
+            158 => Ok(DisconnectReasonCode::SharedSubscriptionNotSupported),
+            159 => Ok(DisconnectReasonCode::ConnectionRateExceeded),
+            160 => Ok(DisconnectReasonCode::MaximumConnectTime),
+            161 => Ok(DisconnectReasonCode::SubscriptionIdentifiersNotSupported),
+            162 => Ok(DisconnectReasonCode::WildcardSubscriptionsNotSupported),
+            _ => Err(Error::InvalidReasonCode(byte)),
         }
     }
 }
@@ -314,13 +331,15 @@ pub enum AuthenticateReasonCode {
     ReAuthenticate = 0x19,
 }
 
-impl From<u8> for AuthenticateReasonCode {
-    fn from(byte: u8) -> Self {
+impl TryFrom<u8> for AuthenticateReasonCode {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
         match byte {
-            0 => AuthenticateReasonCode::Success,
-            24 => AuthenticateReasonCode::ContinueAuthentication,
-            25 => AuthenticateReasonCode::ReAuthenticate,
-            _ => unimplemented!("no other authenticate reason code support"),
+            0 => Ok(AuthenticateReasonCode::Success),
+            24 => Ok(AuthenticateReasonCode::ContinueAuthentication),
+            25 => Ok(AuthenticateReasonCode::ReAuthenticate),
+            _ => Err(Error::InvalidReasonCode(byte)),
         }
     }
 }
@@ -374,32 +393,34 @@ pub enum ConnectReasonCode {
     ConnectionRateExceeded = 0x9F,
 }
 
-impl From<u8> for ConnectReasonCode {
-    fn from(byte: u8) -> Self {
+impl TryFrom<u8> for ConnectReasonCode {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
         match byte {
-            0 => ConnectReasonCode::Success,
-            128 => ConnectReasonCode::UnspecifiedError,
-            129 => ConnectReasonCode::MalformedPacket,
-            130 => ConnectReasonCode::ProtocolError,
-            131 => ConnectReasonCode::ImplementationSpecificError,
-            132 => ConnectReasonCode::UnsupportedProtocolVersion,
-            133 => ConnectReasonCode::ClientIdentifierNotValid,
-            134 => ConnectReasonCode::BadUsernameOrPassword,
-            135 => ConnectReasonCode::NotAuthorized,
-            136 => ConnectReasonCode::ServerUnavailable,
-            137 => ConnectReasonCode::ServerBusy,
-            138 => ConnectReasonCode::Banned,
-            140 => ConnectReasonCode::BadAuthenticationMethod,
-            144 => ConnectReasonCode::TopicNameInvalid,
-            149 => ConnectReasonCode::PacketTooLarge,
-            151 => ConnectReasonCode::QuotaExceeded,
-            153 => ConnectReasonCode::PayloadFormatInvalid,
-            154 => ConnectReasonCode::RetainNotSupported,
-            155 => ConnectReasonCode::QoSNotSupported,
-            156 => ConnectReasonCode::UseAnotherServer,
-            157 => ConnectReasonCode::ServerMoved,
-            159 => ConnectReasonCode::ConnectionRateExceeded,
-            _ => unimplemented!("no other connect reason code support"),
+            0 => Ok(ConnectReasonCode::Success),
+            128 => Ok(ConnectReasonCode::UnspecifiedError),
+            129 => Ok(ConnectReasonCode::MalformedPacket),
+            130 => Ok(ConnectReasonCode::ProtocolError),
+            131 => Ok(ConnectReasonCode::ImplementationSpecificError),
+            132 => Ok(ConnectReasonCode::UnsupportedProtocolVersion),
+            133 => Ok(ConnectReasonCode::ClientIdentifierNotValid),
+            134 => Ok(ConnectReasonCode::BadUsernameOrPassword),
+            135 => Ok(ConnectReasonCode::NotAuthorized),
+            136 => Ok(ConnectReasonCode::ServerUnavailable),
+            137 => Ok(ConnectReasonCode::ServerBusy),
+            138 => Ok(ConnectReasonCode::Banned),
+            140 => Ok(ConnectReasonCode::BadAuthenticationMethod),
+            144 => Ok(ConnectReasonCode::TopicNameInvalid),
+            149 => Ok(ConnectReasonCode::PacketTooLarge),
+            151 => Ok(ConnectReasonCode::QuotaExceeded),
+            153 => Ok(ConnectReasonCode::PayloadFormatInvalid),
+            154 => Ok(ConnectReasonCode::RetainNotSupported),
+            155 => Ok(ConnectReasonCode::QoSNotSupported),
+            156 => Ok(ConnectReasonCode::UseAnotherServer),
+            157 => Ok(ConnectReasonCode::ServerMoved),
+            159 => Ok(ConnectReasonCode::ConnectionRateExceeded),
+            _ => Err(Error::InvalidReasonCode(byte)),
         }
     }
 }
@@ -1,3 +1,4 @@
+#[allow(clippy::upper_case_acronyms)]
 mod url {
     use nom::branch::alt;
     use nom::bytes::complete::{tag, tag_no_case, take};
@@ -75,7 +76,7 @@ mod url {
         i.split_at_position1_complete(
             |item| {
                 let char_item = item.as_char();
-                !(char_item == '-') && !char_item.is_alphanum()
+                char_item != '-' && !char_item.is_alphanum()
             },
             ErrorKind::AlphaNumeric,
         )
@@ -156,7 +157,7 @@ mod url {
         i.split_at_position1_complete(
             |item| {
                 let char_item = item.as_char();
-                !(char_item == '-') && !char_item.is_alphanum() && !(char_item == '.')
+                char_item != '-' && !char_item.is_alphanum() && char_item != '.'
             },
             ErrorKind::AlphaNumeric,
         )
@@ -180,7 +181,7 @@ mod url {
         })
     }
 
-    fn query_params(input: &str) -> Res<&str, QueryParams> {
+    fn query_params(input: &str) -> Res<&str, QueryParams<'_>> {
         context(
             "query params",
             tuple((
@@ -211,7 +212,7 @@ mod url {
             .map(|(next_input, res)| (next_input, res.1))
     }
 
-    fn uri(input: &str) -> Res<&str, URI> {
+    fn uri(input: &str) -> Res<&str, URI<'_>> {
         context(
             "uri",
             tuple((